@@ -1,20 +1,35 @@
-use std::cmp::Ordering;
+use std::cmp::{
+    Ordering,
+    Reverse,
+};
 // Common functions for advent of code 2022.
 use std::{
+    fmt,
     fs,
+    io::{
+        self,
+        Read,
+        Write,
+    },
+    path::{
+        Component,
+        Path,
+        PathBuf,
+    },
+};
+use std::ops::{
+    Index,
+    IndexMut,
 };
 use std::collections::{
+    BinaryHeap,
     HashMap,
     HashSet,
     VecDeque,
 };
 
-use array_tool::vec::{
-    Intersect,
-    Uniq,
-};
+use array_tool::vec::Intersect;
 use itertools::Itertools;
-use regex::Regex;
 
 /// Read the contents of a file directly into a String.
 ///
@@ -28,6 +43,336 @@ pub fn read_file(file_path: &str) -> String {
     fs::read_to_string(file_path).expect("Should have been able to read the file")
 }
 
+/// A 2D grid of cells addressed by `[x, y]`.
+///
+/// Bounded grids own every cell from `[0, 0]` to `[width-1, height-1]` and
+/// nothing outside of that. Infinite grids only store the cells that have been
+/// set and report the `fill` value everywhere else, which suits puzzles that
+/// wander off in any direction.
+#[derive(Clone, Debug)]
+pub struct Grid<T> {
+    cells: GridCells<T>,
+}
+
+#[derive(Clone, Debug)]
+enum GridCells<T> {
+    Bounded { cells: Vec<T>, width: usize, height: usize },
+    Infinite { cells: HashMap<[isize; 2], T>, fill: T },
+}
+
+/// Why a character map didn't make a grid. Lines and columns start at 1.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum GridError {
+    RaggedRow { line: usize, expected: usize, found: usize },
+    BadCell { line: usize, column: usize, character: char },
+}
+
+impl fmt::Display for GridError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::RaggedRow { line, expected, found } => {
+                write!(f, "line {line}: row is {found} wide, expected {expected}")
+            },
+            Self::BadCell { line, column, character } => {
+                write!(f, "line {line}, column {column}: unexpected `{character}`")
+            },
+        }
+    }
+}
+
+impl std::error::Error for GridError {}
+
+/// Orthogonal steps, clockwise from "right".
+pub const NEIGHBORS_4: [[isize; 2]; 4] = [[1, 0], [0, 1], [-1, 0], [0, -1]];
+/// Orthogonal and diagonal steps, clockwise from "right".
+pub const NEIGHBORS_8: [[isize; 2]; 8] = [
+    [1, 0], [1, 1], [0, 1], [-1, 1], [-1, 0], [-1, -1], [0, -1], [1, -1],
+];
+
+impl<T: Clone> Grid<T> {
+    /// A `width` x `height` grid with every cell set to `fill`.
+    pub fn bounded(width: usize, height: usize, fill: T) -> Self {
+        Self {
+            cells: GridCells::Bounded { cells: vec![fill; width*height], width, height },
+        }
+    }
+
+    /// Parse a character map (one row per line, first line is `y = 0`) into a
+    /// bounded grid.
+    ///
+    /// Blank lines are skipped. Panics if the rows aren't all the same length.
+    pub fn from_char_map(map: &str, parse: impl Fn(char) -> T) -> Self {
+        Self::try_from_char_map(map, |character| Some(parse(character)))
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Like `from_char_map`, but `parse` can reject a character (by returning
+    /// None), and bad maps come back as errors instead of panics.
+    pub fn try_from_char_map(map: &str, parse: impl Fn(char) -> Option<T>) -> Result<Self, GridError> {
+        let mut cells: Vec<T> = Vec::new();
+        let mut width: Option<usize> = None;
+        let mut height = 0;
+        for (idx, row) in map.split('\n').map(str::trim).enumerate() {
+            if row.is_empty() {
+                continue
+            }
+            let line = idx + 1;
+            let row_width = row.chars().count();
+            let expected = *width.get_or_insert(row_width);
+            if expected != row_width {
+                return Err(GridError::RaggedRow { line, expected, found: row_width })
+            }
+            for (column, character) in row.chars().enumerate() {
+                let cell = parse(character).ok_or(GridError::BadCell {
+                    line,
+                    column: column + 1,
+                    character,
+                })?;
+                cells.push(cell);
+            }
+            height += 1;
+        }
+        Ok(Self {
+            cells: GridCells::Bounded { cells, width: width.unwrap_or(0), height },
+        })
+    }
+
+    /// Overwrite a cell. Panics if a bounded grid doesn't have the cell.
+    pub fn set(&mut self, coords: [isize; 2], value: T) {
+        match &mut self.cells {
+            GridCells::Bounded { cells, width, height } => {
+                if !Self::in_bounds(coords, *width, *height) {
+                    panic!("{coords:?} is off the grid.");
+                }
+                cells[coords[1] as usize * *width + coords[0] as usize] = value;
+            },
+            GridCells::Infinite { cells, .. } => {
+                cells.insert(coords, value);
+            },
+        }
+    }
+
+    /// Apply `f` to every cell, keeping the shape of the grid.
+    pub fn map<U: Clone>(&self, f: impl Fn(&T) -> U) -> Grid<U> {
+        let cells = match &self.cells {
+            GridCells::Bounded { cells, width, height } => GridCells::Bounded {
+                cells: cells.iter().map(&f).collect(),
+                width: *width,
+                height: *height,
+            },
+            GridCells::Infinite { cells, fill } => GridCells::Infinite {
+                cells: cells.iter().map(|(&coords, value)| (coords, f(value))).collect(),
+                fill: f(fill),
+            },
+        };
+        Grid { cells }
+    }
+}
+
+impl<T> Grid<T> {
+    /// An unbounded grid that reads as `fill` until told otherwise.
+    pub fn infinite(fill: T) -> Self {
+        Self {
+            cells: GridCells::Infinite { cells: HashMap::new(), fill },
+        }
+    }
+
+    fn in_bounds([x, y]: [isize; 2], width: usize, height: usize) -> bool {
+        x >= 0 && y >= 0 && (x as usize) < width && (y as usize) < height
+    }
+
+    pub fn is_bounded(&self) -> bool {
+        matches!(self.cells, GridCells::Bounded { .. })
+    }
+
+    /// Does the grid have this cell at all? Always true for infinite grids.
+    pub fn contains(&self, coords: &[isize; 2]) -> bool {
+        match &self.cells {
+            GridCells::Bounded { width, height, .. } => Self::in_bounds(*coords, *width, *height),
+            GridCells::Infinite { .. } => true,
+        }
+    }
+
+    pub fn get(&self, coords: &[isize; 2]) -> Option<&T> {
+        match &self.cells {
+            GridCells::Bounded { cells, width, height } => {
+                if !Self::in_bounds(*coords, *width, *height) {
+                    return None
+                }
+                cells.get(coords[1] as usize * width + coords[0] as usize)
+            },
+            GridCells::Infinite { cells, fill } => Some(cells.get(coords).unwrap_or(fill)),
+        }
+    }
+
+    /// Return [[min x, min y], [max x, max y]], inclusive.
+    ///
+    /// For infinite grids this covers the cells that have been set. None if
+    /// there are no cells to bound.
+    pub fn bounds(&self) -> Option<[[isize; 2]; 2]> {
+        match &self.cells {
+            GridCells::Bounded { width, height, .. } => {
+                if *width == 0 || *height == 0 {
+                    return None
+                }
+                Some([[0, 0], [*width as isize - 1, *height as isize - 1]])
+            },
+            GridCells::Infinite { cells, .. } => {
+                let (x_min, x_max) = cells.keys().map(|[x, _]| *x).minmax().into_option()?;
+                let (y_min, y_max) = cells.keys().map(|[_, y]| *y).minmax().into_option()?;
+                Some([[x_min, y_min], [x_max, y_max]])
+            },
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.bounds().map_or(0, |[min, max]| (max[0] - min[0] + 1) as usize)
+    }
+
+    pub fn height(&self) -> usize {
+        self.bounds().map_or(0, |[min, max]| (max[1] - min[1] + 1) as usize)
+    }
+
+    /// Every stored cell. Bounded grids go row by row; infinite grids only
+    /// yield the cells that have been set, in no particular order.
+    pub fn iter(&self) -> Box<dyn Iterator<Item = ([isize; 2], &T)> + '_> {
+        match &self.cells {
+            GridCells::Bounded { cells, width, .. } => Box::new(
+                cells
+                    .iter()
+                    .enumerate()
+                    .map(move |(idx, value)| ([(idx % width) as isize, (idx / width) as isize], value))
+            ),
+            GridCells::Infinite { cells, .. } => Box::new(
+                cells.iter().map(|(&coords, value)| (coords, value))
+            ),
+        }
+    }
+
+    /// Count the stored cells matching `predicate`.
+    pub fn count(&self, predicate: impl Fn(&T) -> bool) -> usize {
+        self.iter().filter(|(_, value)| predicate(value)).count()
+    }
+
+    /// Coordinates of the first stored cell matching `predicate`.
+    pub fn position(&self, predicate: impl Fn(&T) -> bool) -> Option<[isize; 2]> {
+        self.iter().find(|(_, value)| predicate(value)).map(|(coords, _)| coords)
+    }
+
+    fn neighbors<'a>(
+        &'a self,
+        [x, y]: [isize; 2],
+        steps: &'a [[isize; 2]],
+    ) -> impl Iterator<Item = [isize; 2]> + 'a {
+        steps
+            .iter()
+            .map(move |[dx, dy]| [x + dx, y + dy])
+            .filter(|neighbor| self.contains(neighbor))
+    }
+
+    /// Orthogonal neighbors that are on the grid.
+    pub fn neighbors4(&self, coords: [isize; 2]) -> impl Iterator<Item = [isize; 2]> + '_ {
+        self.neighbors(coords, &NEIGHBORS_4)
+    }
+
+    /// Orthogonal and diagonal neighbors that are on the grid.
+    pub fn neighbors8(&self, coords: [isize; 2]) -> impl Iterator<Item = [isize; 2]> + '_ {
+        self.neighbors(coords, &NEIGHBORS_8)
+    }
+
+    /// Walk away from `start` (exclusive) one `step` at a time until falling
+    /// off the grid. On an infinite grid that never happens, so `take` what
+    /// you need. A `[0, 0]` step goes nowhere, so yields nothing.
+    pub fn ray(&self, start: [isize; 2], step: [isize; 2]) -> Ray<'_, T> {
+        Ray {
+            grid: self,
+            position: start,
+            step,
+        }
+    }
+
+    /// The cells of row `y`, left to right, across the grid's bounds.
+    pub fn row(&self, y: isize) -> impl Iterator<Item = &T> + '_ {
+        let [[x_min, _], [x_max, _]] = self.bounds().unwrap_or([[0, 0], [-1, -1]]);
+        (x_min..=x_max).filter_map(move |x| self.get(&[x, y]))
+    }
+
+    /// The cells of column `x`, top to bottom, across the grid's bounds.
+    pub fn column(&self, x: isize) -> impl Iterator<Item = &T> + '_ {
+        let [[_, y_min], [_, y_max]] = self.bounds().unwrap_or([[0, 0], [-1, -1]]);
+        (y_min..=y_max).filter_map(move |y| self.get(&[x, y]))
+    }
+
+    /// Draw the grid's bounds, one character per cell.
+    pub fn render(&self, draw: impl Fn([isize; 2], &T) -> char) -> String {
+        match self.bounds() {
+            Some(window) => self.render_window(window, draw),
+            None => String::new(),
+        }
+    }
+
+    /// Draw the cells within `[[min x, min y], [max x, max y]]`, inclusive.
+    /// Cells the grid doesn't have are drawn as spaces.
+    pub fn render_window(
+        &self,
+        [[x_min, y_min], [x_max, y_max]]: [[isize; 2]; 2],
+        draw: impl Fn([isize; 2], &T) -> char,
+    ) -> String {
+        let mut picture = String::new();
+        for y in y_min..=y_max {
+            for x in x_min..=x_max {
+                match self.get(&[x, y]) {
+                    Some(value) => picture.push(draw([x, y], value)),
+                    None => picture.push(' '),
+                }
+            }
+            picture.push('\n');
+        }
+        picture
+    }
+}
+
+impl<T> Index<[isize; 2]> for Grid<T> {
+    type Output = T;
+
+    fn index(&self, coords: [isize; 2]) -> &T {
+        self.get(&coords).unwrap_or_else(|| panic!("{coords:?} is off the grid."))
+    }
+}
+
+impl<T: Clone> IndexMut<[isize; 2]> for Grid<T> {
+    fn index_mut(&mut self, coords: [isize; 2]) -> &mut T {
+        match &mut self.cells {
+            GridCells::Bounded { cells, width, height } => {
+                if !Self::in_bounds(coords, *width, *height) {
+                    panic!("{coords:?} is off the grid.");
+                }
+                &mut cells[coords[1] as usize * *width + coords[0] as usize]
+            },
+            GridCells::Infinite { cells, fill } => cells.entry(coords).or_insert_with(|| fill.clone()),
+        }
+    }
+}
+
+/// Iterator over the cells along a straight line. See `Grid::ray`.
+pub struct Ray<'a, T> {
+    grid: &'a Grid<T>,
+    position: [isize; 2],
+    step: [isize; 2],
+}
+
+impl<'a, T> Iterator for Ray<'a, T> {
+    type Item = ([isize; 2], &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.step == [0, 0] {
+            return None
+        }
+        self.position = [self.position[0] + self.step[0], self.position[1] + self.step[1]];
+        self.grid.get(&self.position).map(|value| (self.position, value))
+    }
+}
+
 /// Day 14: Sand tracking.
 ///
 /// - Sand is falling into a cave.
@@ -48,8 +393,7 @@ enum SpaceType {
 
 /// Tracks spaces that are not air.
 pub struct Cave {
-    // Would be way faster if this was Vec<Vec<>>
-    spaces: HashMap<[isize; 2], SpaceType>,
+    spaces: Grid<SpaceType>,
     sand_source: [isize; 2],
     pub part: u8,
 }
@@ -57,7 +401,7 @@ pub struct Cave {
 impl Cave {
     pub fn new(sand_source: [isize; 2]) -> Self {
         Self {
-            spaces: HashMap::new(),
+            spaces: Grid::infinite(SpaceType::Air),
             sand_source,
             part: 1,
         }
@@ -65,56 +409,45 @@ impl Cave {
 
     /// Return [[L, R], [B, T]]
     fn get_bounds(&self) -> [[isize; 2]; 2] {
-        let l = self.spaces
-            .iter()
-            .filter(|(_, &space)| matches!(space, SpaceType::Rock))
-            .map(|([x, _], _)| *x)
-            .min()
-            .unwrap();
-        let r = self.spaces
+        let rocks = self.spaces
             .iter()
             .filter(|(_, &space)| matches!(space, SpaceType::Rock))
-            .map(|([x, _], _)| *x)
-            .max()
-            .unwrap();
-        let t = self.spaces
-            .iter()
-            .filter(|(_, &space)| matches!(space, SpaceType::Rock))
-            .map(|([_, y], _)| *y)
-            .min()
-            .unwrap();
-        let b = self.spaces
-            .iter()
-            .filter(|(_, &space)| matches!(space, SpaceType::Rock))
-            .map(|([_, y], _)| *y)
-            .max()
-            .unwrap();
+            .map(|(coords, _)| coords);
+        let (mut l, mut r, mut t, mut b) = (isize::MAX, isize::MIN, isize::MAX, isize::MIN);
+        for [x, y] in rocks {
+            l = l.min(x);
+            r = r.max(x);
+            t = t.min(y);
+            b = b.max(y);
+        }
+        if l > r {
+            panic!("No rocks in the cave.");
+        }
         [[l, r], [b, t]]
     }
 
     pub fn print_cave(&self) {
         let bounds = self.get_bounds();
         // println!("{bounds:?}");
-        for y in 0..=bounds[1][0]+3 {
-            for x in bounds[0][0]..=bounds[0][1] {
-                // print!("({x}, {y}); ");
-                if [x, y] == [500, 0] {
-                    print!("+");
-                    continue
-                }
-                match self.get_space(&[x, y]).expect("Bad iterator for printing.") {
-                    SpaceType::Rock => print!("#"),
-                    SpaceType::Sand => print!("o"),
-                    SpaceType::Air => print!("."),
-                }
+        let window = [[bounds[0][0], 0], [bounds[0][1], bounds[1][0]+3]];
+        let picture = self.spaces.render_window(window, |coords, _| {
+            if coords == self.sand_source {
+                return '+'
             }
-            println!();
-        }
+            match self.get_space(&coords) {
+                Some(SpaceType::Rock) => '#',
+                Some(SpaceType::Sand) => 'o',
+                // Air or the abyss.
+                _ => '.',
+            }
+        });
+        print!("{picture}");
     }
 
     fn get_space(&self, coords: &[isize; 2]) -> Option<SpaceType>{
-        if let Some(space) = self.spaces.get(coords) {
-            Some(space.to_owned())
+        let space = self.spaces[*coords];
+        if !matches!(space, SpaceType::Air) {
+            Some(space)
         } else {
             let bounds = self.get_bounds();
 
@@ -168,13 +501,13 @@ impl Cave {
                     // Vertical edge.
                     // This will overwrite every vertex except the first.
                     for y in y[0]..=y[1] {
-                        self.spaces.insert([x[0], y], SpaceType::Rock);
+                        self.spaces.set([x[0], y], SpaceType::Rock);
                     }
                 } else if y[0] == y[1] {
                     // Vertical edge.
                     // This will overwrite every vertex except the first.
                     for x in x[0]..=x[1] {
-                        self.spaces.insert([x, y[0]], SpaceType::Rock);
+                        self.spaces.set([x, y[0]], SpaceType::Rock);
                     }
                 } else {
                     panic!("Overlapping vertices!")
@@ -214,7 +547,7 @@ impl Cave {
                 // The hole be plugged, yo.
                 return false
             }
-            self.spaces.insert(sand_resting_place, SpaceType::Sand);
+            self.spaces.set(sand_resting_place, SpaceType::Sand);
             return true
 
         }
@@ -410,363 +743,2910 @@ fn parse_integer(s: &str) -> PacketValue {
 ///
 /// Elevations `a`-`z` (low to high).
 ///
-/// Motion is orthogonal, no more than one step up in elevation (e.g. a->b,
-/// e->a, ...)
-#[derive(Clone)]
-pub struct MapNode {
-    pub x: isize,
-    pub y: isize,
-    pub elevation: isize,
-    pub distance_from_initial: usize,
-    pub is_visited: bool,
+/// Motion is orthogonal. How far a single step may climb or drop is set by a
+/// `ClimbRule` (the puzzle allows one step up and any drop, e.g. a->b, e->a).
+pub struct HeightMap {
+    elevations: Grid<isize>,
+    pub start: [isize; 2],
+    pub end: [isize; 2],
 }
 
-impl MapNode {
-    pub fn new(x: isize, y: isize, elevation: isize, is_initial: bool) -> Self {
+/// Limits on the change in elevation allowed by a single step.
+#[derive(Clone, Copy, Debug)]
+pub struct ClimbRule {
+    pub max_up: isize,
+    pub max_down: isize,
+}
+
+impl ClimbRule {
+    pub fn new(max_up: isize, max_down: isize) -> Self {
         Self {
-            x,
-            y,
-            elevation,
-            distance_from_initial: if is_initial { 0 } else { usize::MAX },  // Djikstra step 2.
-            is_visited: false,  // Djikstra step 1.
+            max_up,
+            max_down,
         }
     }
 
-    pub fn is_upward_neighbor(&self, other: &Self) -> bool {
-        (((self.x - other.x).abs() == 1 && self.y == other.y)
-        || ((self.y - other.y).abs() ==1 && self.x == other.x))
-        && (other.elevation - self.elevation) <= 1
+    /// Returns true if a step from `from` to `to` elevation is allowed.
+    pub fn allows(&self, from: isize, to: isize) -> bool {
+        to - from <= self.max_up && from - to <= self.max_down
     }
+}
 
-    pub fn is_downward_neighbor(&self, other: &Self) -> bool {
-        (((self.x - other.x).abs() == 1 && self.y == other.y)
-        || ((self.y - other.y).abs() ==1 && self.x == other.x))
-        && (self.elevation - other.elevation) <= 1
+impl Default for ClimbRule {
+    fn default() -> Self {
+        Self::new(1, isize::MAX)
     }
 }
 
-impl Ord for MapNode {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.distance_from_initial.cmp(&other.distance_from_initial)
+impl HeightMap {
+    pub fn parse(height_map: &str) -> Self {
+        let map = Grid::from_char_map(height_map, |character| character);
+        let elevations = map.map(|&character| match character {
+            'S' => 'a' as isize,
+            'E' => 'z' as isize,
+            _ => character as isize,
+        });
+        Self {
+            elevations,
+            start: map.position(|&character| character == 'S').expect("No start (S) on the map."),
+            end: map.position(|&character| character == 'E').expect("No destination (E) on the map."),
+        }
+    }
+
+    pub fn elevation(&self, coords: &[isize; 2]) -> Option<isize> {
+        self.elevations.get(coords).copied()
+    }
+
+    /// Every spot on the map at the given elevation (`S` counts as `a`, `E` as
+    /// `z`).
+    pub fn cells_at_elevation(&self, elevation: char) -> Vec<[isize; 2]> {
+        self.elevations
+            .iter()
+            .filter(|(_, &e)| e == elevation as isize)
+            .map(|(coords, _)| coords)
+            .collect()
+    }
+
+    /// Orthogonal neighbors that can be stepped to from `coords`.
+    fn reachable_neighbors(&self, coords: [isize; 2], rule: &ClimbRule) -> Vec<[isize; 2]> {
+        let here = self.elevations[coords];
+        self.elevations
+            .neighbors4(coords)
+            .filter(|&neighbor| rule.allows(here, self.elevations[neighbor]))
+            .collect()
+    }
+
+    /// A* search from any of `sources` to `target`.
+    ///
+    /// All sources go into the open set at distance 0, so a multi-source
+    /// search (e.g. every `a` at once) costs the same as a single one. Every
+    /// step costs 1, so the Manhattan distance to the target never overshoots
+    /// and the first time the target comes off the heap is the shortest path.
+    ///
+    /// Returns the path from the chosen source to the target, inclusive, or
+    /// None if the target can't be reached.
+    pub fn find_path(
+        &self,
+        sources: &[[isize; 2]],
+        target: [isize; 2],
+        rule: &ClimbRule,
+    ) -> Option<Vec<[isize; 2]>> {
+        let heuristic = |[x, y]: [isize; 2]| {
+            ((x - target[0]).abs() + (y - target[1]).abs()) as usize
+        };
+        // Ordered by (estimated total, distance so far, coords). Reverse
+        // because BinaryHeap is a max-heap.
+        let mut open: BinaryHeap<Reverse<(usize, usize, [isize; 2])>> = BinaryHeap::new();
+        let mut best_distance: HashMap<[isize; 2], usize> = HashMap::new();
+        let mut came_from: HashMap<[isize; 2], [isize; 2]> = HashMap::new();
+        for &source in sources {
+            if self.elevation(&source).is_none() {
+                continue
+            }
+            best_distance.insert(source, 0);
+            open.push(Reverse((heuristic(source), 0, source)));
+        }
+
+        while let Some(Reverse((_, distance, coords))) = open.pop() {
+            if coords == target {
+                let mut path = vec![coords];
+                let mut current = coords;
+                while let Some(&previous) = came_from.get(&current) {
+                    path.push(previous);
+                    current = previous;
+                }
+                path.reverse();
+                return Some(path)
+            }
+            if distance > best_distance[&coords] {
+                // Stale entry; this spot was reached faster since.
+                continue
+            }
+            for neighbor in self.reachable_neighbors(coords, rule) {
+                let neighbor_distance = distance + 1;
+                if neighbor_distance < *best_distance.get(&neighbor).unwrap_or(&usize::MAX) {
+                    best_distance.insert(neighbor, neighbor_distance);
+                    came_from.insert(neighbor, coords);
+                    open.push(Reverse((neighbor_distance + heuristic(neighbor), neighbor_distance, neighbor)));
+                }
+            }
+        }
+        None
+    }
+
+    /// Number of steps on the shortest path from any of `sources` to `target`.
+    pub fn shortest_distance(
+        &self,
+        sources: &[[isize; 2]],
+        target: [isize; 2],
+        rule: &ClimbRule,
+    ) -> Option<usize> {
+        self.find_path(sources, target, rule).map(|path| path.len() - 1)
     }
 }
 
-impl PartialOrd for MapNode {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(self.cmp(other))
+const GLYPH_WIDTH: usize = 4;
+const GLYPH_HEIGHT: usize = 6;
+/// Columns from the start of one letter to the start of the next.
+const GLYPH_PITCH: usize = GLYPH_WIDTH + 1;
+/// The puzzle font: 4x6 capital letters, drawn row by row.
+const GLYPHS: [(char, [&str; GLYPH_HEIGHT]); 17] = [
+    ('A', [".##.", "#..#", "#..#", "####", "#..#", "#..#"]),
+    ('B', ["###.", "#..#", "###.", "#..#", "#..#", "###."]),
+    ('C', [".##.", "#..#", "#...", "#...", "#..#", ".##."]),
+    ('E', ["####", "#...", "###.", "#...", "#...", "####"]),
+    ('F', ["####", "#...", "###.", "#...", "#...", "#..."]),
+    ('G', [".##.", "#..#", "#...", "#.##", "#..#", ".###"]),
+    ('H', ["#..#", "#..#", "####", "#..#", "#..#", "#..#"]),
+    ('I', [".###", "..#.", "..#.", "..#.", "..#.", ".###"]),
+    ('J', ["..##", "...#", "...#", "...#", "#..#", ".##."]),
+    ('K', ["#..#", "#.#.", "##..", "#.#.", "#.#.", "#..#"]),
+    ('L', ["#...", "#...", "#...", "#...", "#...", "####"]),
+    ('O', [".##.", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('P', ["###.", "#..#", "#..#", "###.", "#...", "#..."]),
+    ('R', ["###.", "#..#", "#..#", "###.", "#.#.", "#..#"]),
+    ('S', [".###", "#...", "#...", ".##.", "...#", "###."]),
+    ('U', ["#..#", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('Z', ["####", "...#", "..#.", ".#..", "#...", "####"]),
+];
+
+/// Asked a `CRT` for a frame it hasn't drawn.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NoSuchFrame {
+    pub frame: usize,
+    pub n_frames: usize,
+}
+
+impl fmt::Display for NoSuchFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "no frame {}, there are only {}", self.frame, self.n_frames)
     }
 }
 
-impl PartialEq for MapNode {
-    fn eq(&self, other: &Self) -> bool {
-        self.distance_from_initial == other.distance_from_initial
+impl std::error::Error for NoSuchFrame {}
+
+impl From<NoSuchFrame> for io::Error {
+    fn from(e: NoSuchFrame) -> Self {
+        io::Error::new(io::ErrorKind::InvalidInput, e)
     }
 }
 
-impl Eq for MapNode {}
+/// Why `CRT::read_letters` couldn't read a frame.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OcrError {
+    NoSuchFrame { frame: usize, n_frames: usize },
+    /// `partial` is the best reading available, with `?` for each glyph in
+    /// `unrecognized` (positions counting from 0 on the left).
+    Unrecognized { partial: String, unrecognized: Vec<usize> },
+}
 
-/// Return the node with the minimum estimated distance to initial.
-pub fn get_next_node_coords(nodes: &HashMap<[isize; 2], MapNode>) -> Option<[isize; 2]>{
-    nodes.values().filter(|&node| !node.is_visited).min().map(|node| [node.x, node.y])
+impl fmt::Display for OcrError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NoSuchFrame { frame, n_frames } => write!(f, "no frame {frame}, there are only {n_frames}"),
+            Self::Unrecognized { partial, unrecognized } => {
+                write!(f, "couldn't read glyphs {unrecognized:?} in \"{partial}\"")
+            },
+        }
+    }
 }
 
-/// Djikstra's algorithm.
-///
-/// Nodes have already been marked visited or nots
+impl std::error::Error for OcrError {}
 
-/// Going to try Djikstra's algorithm
-///
-///
+impl From<NoSuchFrame> for OcrError {
+    fn from(NoSuchFrame { frame, n_frames }: NoSuchFrame) -> Self {
+        Self::NoSuchFrame { frame, n_frames }
+    }
+}
 
 /// CRT for the comms device
 ///
-/// Each clock cycle, draws a single pixel on a 40x6 display. hi/lo is
-/// determined by the location of a 3-wide sprite (CPU x +/- 1)
+/// Each clock cycle, draws a single pixel on a `width` x `height` display,
+/// left to right and top to bottom. hi/lo is determined by the location of the
+/// sprite, which sits at CPU x on the row being drawn. `sprite` lists the
+/// columns it covers relative to x; the puzzle's is 3 wide (x +/- 1).
+///
+/// Once the screen is full the beam wraps around and starts a new frame.
 pub struct CRT {
-    display: Vec<bool>,
+    frames: Vec<Vec<bool>>,
     width: usize,
     height: usize,
+    sprite: Vec<isize>,
+    // Every pixel the beam has drawn since replay was enabled, in order:
+    // (frame, pixel, lit).
+    history: Option<Vec<(usize, usize, bool)>>,
 }
 
 impl CRT {
-    pub fn new() -> Self {
-        let width = 40;
-        let height = 6;
+    pub fn new(width: usize, height: usize, sprite: Vec<isize>) -> Self {
+        if width == 0 || height == 0 {
+            panic!("A {width}x{height} screen is a bit small.");
+        }
         Self {
-            display: vec![false; width*height],
+            frames: vec![vec![false; width*height]],
             width,
             height,
+            sprite,
+            history: None,
         }
     }
 
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Number of frames drawn on so far (always at least one).
+    pub fn n_frames(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Pixels of a frame, row by row.
+    pub fn frame(&self, frame_idx: usize) -> Option<&[bool]> {
+        self.frames.get(frame_idx).map(Vec::as_slice)
+    }
+
+    fn existing_frame(&self, frame_idx: usize) -> Result<&[bool], NoSuchFrame> {
+        self.frame(frame_idx).ok_or(NoSuchFrame { frame: frame_idx, n_frames: self.n_frames() })
+    }
+
+    /// Start remembering every pixel the beam draws, for `replay_frames`.
+    pub fn enable_replay(&mut self) {
+        self.history.get_or_insert_with(Vec::new);
+    }
+
+    /// Stop remembering and throw away what was remembered.
+    pub fn disable_replay(&mut self) {
+        self.history = None;
+    }
+
     /// Run once per cycle. Draws on a lag (cycle 1 draws pixel 0).
     pub fn lazer_beam_it(&mut self, cycle: usize, sprite_pos: isize) {
-        if cycle >= self.width * self.height {
-            return
+        if cycle == 0 {
+            panic!("The beam doesn't draw until cycle 1.");
+        }
+        let screen_size = self.width * self.height;
+        let frame_idx = (cycle-1) / screen_size;
+        let pixel_idx = (cycle-1) % screen_size;
+        while self.frames.len() <= frame_idx {
+            self.frames.push(vec![false; screen_size]);
+        }
+        let crt_pos = (pixel_idx % self.width) as isize;
+        let lit = self.sprite
+            .iter()
+            .any(|offset| sprite_pos + offset == crt_pos);
+        self.frames[frame_idx][pixel_idx] = lit;
+        if let Some(history) = &mut self.history {
+            history.push((frame_idx, pixel_idx, lit));
         }
-        let crt_pos = (cycle-1) % 40;
-        self.display[cycle-1] = (crt_pos as isize - sprite_pos).abs() <= 1;
     }
 
-    pub fn visualize(&self) {
-        for row in &self.display.iter().chunks(self.width) {
-            for &pixel in row {
-                if pixel {
-                    print!("#");
-                    continue
-                }
-                print!(".")
+    /// Read a frame as text in the puzzle font.
+    ///
+    /// Glyphs are 4x6 with a blank column between them, starting from the
+    /// left edge; an all-blank glyph reads as a space.
+    pub fn read_letters(&self, frame_idx: usize) -> Result<String, OcrError> {
+        let frame = self.existing_frame(frame_idx)?;
+        let mut partial = String::new();
+        let mut unrecognized: Vec<usize> = Vec::new();
+        for glyph_idx in 0..(self.width + 1) / GLYPH_PITCH {
+            let left = glyph_idx * GLYPH_PITCH;
+            let glyph: Vec<String> = (0..self.height.min(GLYPH_HEIGHT))
+                .map(|row| {
+                    frame[row*self.width + left..row*self.width + left + GLYPH_WIDTH]
+                        .iter()
+                        .map(|&pixel| if pixel { '#' } else { '.' })
+                        .collect()
+                })
+                .collect();
+            if glyph.iter().all(|row| !row.contains('#')) {
+                partial.push(' ');
+                continue
+            }
+            let letter = GLYPHS
+                .iter()
+                .find(|(_, rows)| glyph.iter().eq(rows.iter()))
+                .map(|(letter, _)| *letter);
+            match letter {
+                Some(letter) => partial.push(letter),
+                None => {
+                    partial.push('?');
+                    unrecognized.push(glyph_idx);
+                },
             }
-            println!()
+        }
+        if unrecognized.is_empty() {
+            Ok(partial)
+        } else {
+            Err(OcrError::Unrecognized { partial, unrecognized })
         }
     }
-}
 
-impl Default for CRT {
-    fn default() -> Self {
-        Self::new()
+    /// The frame as a plain (P1) PBM image. Lit pixels are black, to match
+    /// the `#`s from `visualize`.
+    pub fn to_pbm(&self, frame_idx: usize) -> Result<String, NoSuchFrame> {
+        let frame = self.existing_frame(frame_idx)?;
+        let mut image = format!("P1\n{} {}\n", self.width, self.height);
+        for row in frame.chunks(self.width) {
+            let row: Vec<&str> = row.iter().map(|&pixel| if pixel { "1" } else { "0" }).collect();
+            image.push_str(&row.join(" "));
+            image.push('\n');
+        }
+        Ok(image)
     }
-}
 
-/// CPU for the comms device
-///
-/// noop : 1 cycle
-/// addx : 2 cycles (value changes *after* cycle ends)
-pub struct CPU {
-    pub x: isize,
-    pub cycle: usize,
-    queue: VecDeque<isize>,  // Stores delta x for upcoming clock cycles.
-}
+    pub fn write_pbm(&self, frame_idx: usize, file_path: &str) -> io::Result<()> {
+        fs::write(file_path, self.to_pbm(frame_idx)?)
+    }
 
-impl CPU {
-    pub fn new() -> Self {
-        Self {
-            x: 1,
-            // zeroth cycle is a no-op.
-            cycle: 0,
-            queue: VecDeque::from([0]),
+    /// The frame as a grayscale PNG, each pixel blown up to a `scale` x `scale`
+    /// square so it's actually visible. Lit pixels are black.
+    pub fn to_png(&self, frame_idx: usize, scale: usize) -> Result<Vec<u8>, NoSuchFrame> {
+        let frame = self.existing_frame(frame_idx)?;
+        let scale = scale.max(1);
+        let (width, height) = (self.width * scale, self.height * scale);
+        // Each scanline starts with a filter type byte (0 = none).
+        let mut scanlines: Vec<u8> = Vec::with_capacity((width + 1) * height);
+        for row in frame.chunks(self.width) {
+            let mut scanline: Vec<u8> = vec![0];
+            for &pixel in row {
+                scanline.extend(std::iter::repeat_n(if pixel { 0 } else { 255 }, scale));
+            }
+            for _ in 0..scale {
+                scanlines.extend_from_slice(&scanline);
+            }
         }
+
+        let mut header: Vec<u8> = Vec::new();
+        header.extend((width as u32).to_be_bytes());
+        header.extend((height as u32).to_be_bytes());
+        // 8-bit grayscale, default compression/filter, not interlaced.
+        header.extend([8, 0, 0, 0, 0]);
+
+        let mut png: Vec<u8> = b"\x89PNG\r\n\x1a\n".to_vec();
+        push_png_chunk(&mut png, b"IHDR", &header);
+        push_png_chunk(&mut png, b"IDAT", &zlib_stored(&scanlines));
+        push_png_chunk(&mut png, b"IEND", &[]);
+        Ok(png)
     }
 
-    /// Queue up a no-op.
-    pub fn noop(&mut self) {
-        self.queue.push_back(0);
+    pub fn write_png(&self, frame_idx: usize, scale: usize, file_path: &str) -> io::Result<()> {
+        fs::write(file_path, self.to_png(frame_idx, scale)?)
     }
 
-    /// Queue up an add operation.
-    pub fn addx(&mut self, dx: isize) {
-        self.queue.push_back(0);
-        self.queue.push_back(dx);
+    /// Draw the frame with Unicode half blocks, packing two pixel rows into
+    /// each line of text so the screen keeps roughly its real aspect ratio.
+    pub fn render_half_blocks(&self, frame_idx: usize) -> Result<String, NoSuchFrame> {
+        Ok(half_blocks(self.existing_frame(frame_idx)?, self.width))
     }
 
-    pub fn get_runtime_in_cycles(&self) -> usize {
-        self.queue.len()
+    /// Every step of drawing the screen since `enable_replay`: one half-block
+    /// picture per cycle, showing the frame the beam was on as it stood after
+    /// that cycle. Pixels drawn before replay was enabled show as unlit.
+    pub fn replay_frames(&self) -> Vec<String> {
+        let history = self.history.as_deref().unwrap_or(&[]);
+        let mut frames: Vec<Vec<bool>> = Vec::new();
+        let mut pictures: Vec<String> = Vec::with_capacity(history.len());
+        for &(frame_idx, pixel_idx, lit) in history {
+            while frames.len() <= frame_idx {
+                frames.push(vec![false; self.width * self.height]);
+            }
+            frames[frame_idx][pixel_idx] = lit;
+            pictures.push(half_blocks(&frames[frame_idx], self.width));
+        }
+        pictures
     }
 
-    /// Executes a clock cycle if there's an instruction ready.
-    ///
-    /// Returns the current cycle, either as an Ok or an Err.
-    pub fn execute_clock_cycle(&mut self) -> Result<usize, usize>{
-        match self.queue.pop_front() {
-            Some(dx) => {
-                self.x += dx;
-                self.cycle += 1;
-                Ok(self.cycle)
-            },
-            None => {
-                Err(self.cycle)
+    /// Play back `replay_frames` in the terminal, redrawing in place.
+    pub fn replay(&self, delay: std::time::Duration) {
+        // Clear the screen once, then just send the cursor home each frame.
+        print!("\x1b[2J");
+        for picture in self.replay_frames() {
+            print!("\x1b[H{picture}");
+            io::stdout().flush().expect("Couldn't draw to the terminal.");
+            std::thread::sleep(delay);
+        }
+    }
+
+    /// Every frame as `#`/`.` text, with a blank line between frames.
+    pub fn render(&self) -> String {
+        let mut picture = String::new();
+        for (frame_idx, frame) in self.frames.iter().enumerate() {
+            if frame_idx > 0 {
+                picture.push('\n');
+            }
+            for row in frame.chunks(self.width) {
+                picture.extend(row.iter().map(|&pixel| if pixel { '#' } else { '.' }));
+                picture.push('\n');
             }
         }
+        picture
     }
 
-    pub fn get_signal_strength(&self) -> isize {
-        self.cycle as isize * self.x
+    /// Print every frame, with a blank line between frames.
+    pub fn visualize(&self) {
+        print!("{}", self.render());
     }
 }
 
-impl Default for CPU {
+impl Default for CRT {
     fn default() -> Self {
-        Self::new()
+        Self::new(40, 6, vec![-1, 0, 1])
     }
+}
 
+/// Two pixel rows per line of text: top half, bottom half, both, or neither.
+fn half_blocks(frame: &[bool], width: usize) -> String {
+    let rows: Vec<&[bool]> = frame.chunks(width).collect();
+    let mut picture = String::new();
+    for pair in rows.chunks(2) {
+        for x in 0..width {
+            let top = pair[0][x];
+            let bottom = pair.get(1).is_some_and(|row| row[x]);
+            picture.push(match (top, bottom) {
+                (true, true) => '█',
+                (true, false) => '▀',
+                (false, true) => '▄',
+                (false, false) => ' ',
+            });
+        }
+        picture.push('\n');
+    }
+    picture
+}
 
+/// Append a PNG chunk: length, type, data, CRC of type and data.
+fn push_png_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend((data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend(crc.to_be_bytes());
 }
 
-/// Rope end structure to track position on a grid.
-///
-/// Initial position is 0, 0.
-pub struct RopeEnd {
-    pub x: isize,
+/// Wrap bytes in a zlib stream without compressing them (deflate "stored"
+/// blocks). Screens are tiny, so it's not worth pulling in a compressor.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    // CMF/FLG: deflate with a 32K window, no dictionary, checksum-friendly.
+    let mut stream: Vec<u8> = vec![0x78, 0x01];
+    let mut blocks = data.chunks(u16::MAX as usize).peekable();
+    if blocks.peek().is_none() {
+        // Still need one (final, empty) block.
+        stream.extend([1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        let is_final = blocks.peek().is_none();
+        let len = block.len() as u16;
+        stream.push(is_final as u8);
+        stream.extend(len.to_le_bytes());
+        stream.extend((!len).to_le_bytes());
+        stream.extend_from_slice(block);
+    }
+    stream.extend(adler32(data).to_be_bytes());
+    stream
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc: u32 = 0xffff_ffff;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b): (u32, u32) = (1, 0);
+    for &byte in bytes {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+/// Registers on the comms device CPU.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Register {
+    X,
+    Y,
+}
+
+impl Register {
+    pub const ALL: [Self; 2] = [Self::X, Self::Y];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+
+    fn parse(source: &str, line: usize) -> Result<Self, ProgramError> {
+        match source {
+            "x" => Ok(Self::X),
+            "y" => Ok(Self::Y),
+            _ => Err(ProgramError::BadOperand {
+                line,
+                operand: source.to_string(),
+            }),
+        }
+    }
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::X => write!(f, "x"),
+            Self::Y => write!(f, "y"),
+        }
+    }
+}
+
+/// One line of a program for the comms device CPU.
+///
+/// Jump offsets are relative to the jump itself, so `jmp 1` is a fancy noop
+/// and `jmp -1` goes back one instruction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Instruction {
+    Noop,
+    Addx(isize),
+    Addy(isize),
+    Mulx(isize),
+    Jmp(isize),
+    /// Jump if the register is zero.
+    Jz(Register, isize),
+    /// Jump if the register is not zero.
+    Jnz(Register, isize),
+}
+
+impl Instruction {
+    /// Parse a single line of source, e.g. `addx -3`. `line` is only used for
+    /// error reporting.
+    pub fn parse(source: &str, line: usize) -> Result<Self, ProgramError> {
+        let mut parts = source.split_whitespace();
+        let opcode = parts.next().unwrap_or("");
+        let mut next_operand = || parts.next().ok_or(ProgramError::MissingOperand {
+            line,
+            opcode: opcode.to_string(),
+        });
+        let instruction = match opcode {
+            "noop" => Self::Noop,
+            "addx" => Self::Addx(parse_operand(next_operand()?, line)?),
+            "addy" => Self::Addy(parse_operand(next_operand()?, line)?),
+            "mulx" => Self::Mulx(parse_operand(next_operand()?, line)?),
+            "jmp" => Self::Jmp(parse_operand(next_operand()?, line)?),
+            "jz" => Self::Jz(
+                Register::parse(next_operand()?, line)?,
+                parse_operand(next_operand()?, line)?,
+            ),
+            "jnz" => Self::Jnz(
+                Register::parse(next_operand()?, line)?,
+                parse_operand(next_operand()?, line)?,
+            ),
+            _ => {
+                return Err(ProgramError::UnknownOpcode {
+                    line,
+                    opcode: opcode.to_string(),
+                })
+            },
+        };
+        // Anything left over is a typo waiting to happen.
+        if let Some(extra) = parts.next() {
+            return Err(ProgramError::BadOperand {
+                line,
+                operand: extra.to_string(),
+            })
+        }
+        Ok(instruction)
+    }
+
+    /// How many clock cycles the instruction occupies.
+    pub fn cycles(&self) -> usize {
+        match self {
+            Self::Noop | Self::Jmp(_) => 1,
+            Self::Addx(_) | Self::Addy(_) | Self::Jz(..) | Self::Jnz(..) => 2,
+            Self::Mulx(_) => 3,
+        }
+    }
+
+    /// Does this instruction ever jump somewhere other than the next line?
+    pub fn is_jump(&self) -> bool {
+        matches!(self, Self::Jmp(_) | Self::Jz(..) | Self::Jnz(..))
+    }
+
+    /// Apply the instruction's effect, which happens once its last cycle ends.
+    /// `registers` is indexed in `Register::ALL` order.
+    ///
+    /// Returns the offset to the next instruction, or a fault if a register
+    /// over/underflows.
+    pub fn execute(&self, registers: &mut [isize; Register::ALL.len()]) -> Result<isize, CpuFault> {
+        let mut update = |register: Register, op: fn(isize, isize) -> Option<isize>, operand: isize| {
+            let value = &mut registers[register.index()];
+            *value = op(*value, operand).ok_or(CpuFault::Overflow { register })?;
+            Ok(1)
+        };
+        match *self {
+            Self::Noop => Ok(1),
+            Self::Addx(dx) => update(Register::X, isize::checked_add, dx),
+            Self::Addy(dy) => update(Register::Y, isize::checked_add, dy),
+            Self::Mulx(factor) => update(Register::X, isize::checked_mul, factor),
+            Self::Jmp(offset) => Ok(offset),
+            Self::Jz(register, offset) => {
+                Ok(if registers[register.index()] == 0 { offset } else { 1 })
+            },
+            Self::Jnz(register, offset) => {
+                Ok(if registers[register.index()] != 0 { offset } else { 1 })
+            },
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Noop => write!(f, "noop"),
+            Self::Addx(dx) => write!(f, "addx {dx}"),
+            Self::Addy(dy) => write!(f, "addy {dy}"),
+            Self::Mulx(factor) => write!(f, "mulx {factor}"),
+            Self::Jmp(offset) => write!(f, "jmp {offset}"),
+            Self::Jz(register, offset) => write!(f, "jz {register} {offset}"),
+            Self::Jnz(register, offset) => write!(f, "jnz {register} {offset}"),
+        }
+    }
+}
+
+fn parse_operand(operand: &str, line: usize) -> Result<isize, ProgramError> {
+    operand.parse::<isize>().map_err(|_| ProgramError::BadOperand {
+        line,
+        operand: operand.to_string(),
+    })
+}
+
+/// Why a program didn't assemble. Line numbers start at 1.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ProgramError {
+    UnknownOpcode { line: usize, opcode: String },
+    MissingOperand { line: usize, opcode: String },
+    BadOperand { line: usize, operand: String },
+}
+
+impl fmt::Display for ProgramError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::UnknownOpcode { line, opcode } => {
+                write!(f, "line {line}: unknown opcode `{opcode}`")
+            },
+            Self::MissingOperand { line, opcode } => {
+                write!(f, "line {line}: `{opcode}` needs an operand")
+            },
+            Self::BadOperand { line, operand } => {
+                write!(f, "line {line}: bad operand `{operand}`")
+            },
+        }
+    }
+}
+
+impl std::error::Error for ProgramError {}
+
+/// Assemble program source, one instruction per line. Blank lines are skipped
+/// but still counted for line numbers.
+pub fn parse_program(source: &str) -> Result<Vec<Instruction>, ProgramError> {
+    source
+        .split('\n')
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(idx, line)| Instruction::parse(line, idx + 1))
+        .collect()
+}
+
+/// Somewhere for the CPU debugger to stop.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Breakpoint {
+    /// Stop during this cycle.
+    Cycle(usize),
+    /// Stop during any cycle where comparing the register to the value gives
+    /// this ordering, e.g. `Register(Register::X, Ordering::Less, 0)` stops
+    /// whenever x is negative.
+    Register(Register, Ordering, isize),
+}
+
+impl Breakpoint {
+    fn is_hit(&self, cpu: &CPU) -> bool {
+        match *self {
+            Self::Cycle(cycle) => cpu.cycle == cycle,
+            Self::Register(register, ordering, value) => cpu.register(register).cmp(&value) == ordering,
+        }
+    }
+}
+
+/// Why the CPU handed control back from `step` or `resume`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    /// Ran a cycle and nothing interesting happened.
+    Step,
+    Breakpoint(Breakpoint),
+    /// The CPU stopped running; see `RunState` for why.
+    Stopped(RunState),
+}
+
+/// Whether the CPU can keep going.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RunState {
+    Running,
+    /// Ran off the end of the program, which is how programs finish.
+    Halted,
+    /// Something went wrong; the CPU won't run any more until it's reset.
+    Faulted(CpuFault),
+}
+
+/// What went wrong when the CPU faulted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CpuFault {
+    /// The instruction at `from` jumped outside the program. Landing just
+    /// past the last instruction is fine; that's how programs halt.
+    JumpOutOfBounds { from: usize, to: isize },
+    /// A register over/underflowed.
+    Overflow { register: Register },
+    /// Still going after `limit` cycles, so probably stuck in a loop.
+    CycleLimit { limit: usize },
+}
+
+impl fmt::Display for CpuFault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::JumpOutOfBounds { from, to } => {
+                write!(f, "instruction {from} jumped out of the program (to {to})")
+            },
+            Self::Overflow { register } => write!(f, "register {register} overflowed"),
+            Self::CycleLimit { limit } => write!(f, "still running after {limit} cycles"),
+        }
+    }
+}
+
+impl std::error::Error for CpuFault {}
+
+/// What the CPU was up to during a single cycle.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceEntry {
+    pub cycle: usize,
+    pub instruction: Instruction,
+    pub registers: [isize; Register::ALL.len()],
+}
+
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:>6}  {:<12}", self.cycle, self.instruction.to_string())?;
+        for register in Register::ALL {
+            write!(f, " {register}={}", self.registers[register.index()])?;
+        }
+        Ok(())
+    }
+}
+
+/// CPU for the comms device
+///
+/// Fetches one instruction at a time and holds it for as many cycles as it
+/// costs (see `Instruction::cycles`). Register changes land *after* the last
+/// cycle ends, so during a cycle the registers still hold the old values.
+///
+/// noop : 1 cycle
+/// addx : 2 cycles
+/// addy : 2 cycles
+/// mulx : 3 cycles
+/// jmp  : 1 cycle
+/// jz   : 2 cycles
+/// jnz  : 2 cycles
+///
+/// Jumps make it easy to loop forever, so a program that's still going after
+/// `cycle_limit` cycles faults with `CpuFault::CycleLimit`.
+pub struct CPU {
+    registers: [isize; Register::ALL.len()],
+    pub cycle: usize,
+    cycle_limit: usize,
+    program: Vec<Instruction>,
+    pc: usize,
+    // The instruction being executed and how many of its cycles are left.
+    current: Option<(Instruction, usize)>,
+    state: RunState,
+    breakpoints: Vec<Breakpoint>,
+    trace: Option<TraceRecorder>,
+}
+
+impl CPU {
+    /// Plenty for any puzzle input.
+    pub const DEFAULT_CYCLE_LIMIT: usize = 1_000_000;
+
+    pub fn new() -> Self {
+        Self {
+            // x starts at 1, everything else at 0.
+            registers: [1, 0],
+            cycle: 0,
+            cycle_limit: Self::DEFAULT_CYCLE_LIMIT,
+            program: Vec::new(),
+            pc: 0,
+            current: None,
+            state: RunState::Running,
+            breakpoints: Vec::new(),
+            trace: None,
+        }
+    }
+
+    /// Queue up a no-op.
+    pub fn noop(&mut self) {
+        self.program.push(Instruction::Noop);
+    }
+
+    /// Queue up an add operation.
+    pub fn addx(&mut self, dx: isize) {
+        self.program.push(Instruction::Addx(dx));
+    }
+
+    /// Queue up every instruction in a program, after anything already queued.
+    pub fn load_program(&mut self, program: &[Instruction]) {
+        self.program.extend_from_slice(program);
+    }
+
+    /// Put the CPU back how it was before the first cycle, keeping the
+    /// program and breakpoints. A trace, if enabled, starts over.
+    pub fn reset(&mut self) {
+        self.registers = Self::new().registers;
+        self.cycle = 0;
+        self.pc = 0;
+        self.current = None;
+        self.state = RunState::Running;
+        if let Some(trace) = &mut self.trace {
+            trace.clear();
+        }
+    }
+
+    pub fn cycle_limit(&self) -> usize {
+        self.cycle_limit
+    }
+
+    /// How many cycles a program gets before it's assumed to be stuck.
+    pub fn set_cycle_limit(&mut self, limit: usize) {
+        self.cycle_limit = limit;
+    }
+
+    /// Swap in a different program and reset, ready to run it.
+    pub fn reload_program(&mut self, program: &[Instruction]) {
+        self.program = program.to_vec();
+        self.reset();
+    }
+
+    pub fn state(&self) -> RunState {
+        self.state
+    }
+
+    pub fn register(&self, register: Register) -> isize {
+        self.registers[register.index()]
+    }
+
+    /// How many more cycles will run before the program ends, counting the
+    /// rest of the current instruction.
+    ///
+    /// None if there's a jump left to run, since then there's no telling
+    /// without running it.
+    pub fn remaining_cycles(&self) -> Option<usize> {
+        if self.state != RunState::Running {
+            return Some(0)
+        }
+        let (in_flight, next_pc) = match self.current {
+            Some((instruction, _)) if instruction.is_jump() => return None,
+            Some((_, cycles_left)) => (cycles_left, self.pc + 1),
+            None => (0, self.pc),
+        };
+        let mut remaining = in_flight;
+        for instruction in self.program.iter().skip(next_pc) {
+            if instruction.is_jump() {
+                return None
+            }
+            remaining += instruction.cycles();
+        }
+        Some(remaining)
+    }
+
+    /// Executes a clock cycle if there's an instruction ready.
+    ///
+    /// Returns `Running` if a cycle ran (`cycle` says which). Once the CPU
+    /// halts or faults it stays that way until `reset`, and further calls
+    /// don't advance the clock.
+    pub fn execute_clock_cycle(&mut self) -> RunState {
+        if self.state != RunState::Running {
+            return self.state
+        }
+        // Out of cycles, unless all that's left is to halt. Checked before
+        // touching anything, so the CPU is left as it was after the last cycle.
+        if self.cycle >= self.cycle_limit && !self.is_finishing() {
+            self.state = RunState::Faulted(CpuFault::CycleLimit { limit: self.cycle_limit });
+            return self.state
+        }
+        // Finish off the instruction whose last cycle just ended.
+        if let Some((instruction, 0)) = self.current {
+            self.current = None;
+            match instruction.execute(&mut self.registers) {
+                Ok(offset) => match self.pc.checked_add_signed(offset).filter(|&pc| pc <= self.program.len()) {
+                    Some(pc) => self.pc = pc,
+                    None => {
+                        self.state = RunState::Faulted(CpuFault::JumpOutOfBounds {
+                            from: self.pc,
+                            to: self.pc as isize + offset,
+                        });
+                        return self.state
+                    },
+                },
+                Err(fault) => {
+                    self.state = RunState::Faulted(fault);
+                    return self.state
+                },
+            }
+        }
+        if self.current.is_none() {
+            match self.program.get(self.pc) {
+                Some(&instruction) => self.current = Some((instruction, instruction.cycles())),
+                None => {
+                    self.state = RunState::Halted;
+                    return self.state
+                },
+            }
+        }
+        if let Some((_, cycles_left)) = &mut self.current {
+            *cycles_left -= 1;
+        }
+        self.cycle += 1;
+        if let Some(mut trace) = self.trace.take() {
+            trace.record(self);
+            self.trace = Some(trace);
+        }
+        RunState::Running
+    }
+
+    /// Will the next `execute_clock_cycle` halt the program rather than run a
+    /// cycle? Works it out on the side, without changing anything.
+    fn is_finishing(&self) -> bool {
+        let next_pc = match self.current {
+            Some((instruction, 0)) => {
+                let mut registers = self.registers;
+                match instruction.execute(&mut registers) {
+                    Ok(offset) => self.pc.checked_add_signed(offset),
+                    Err(_) => return false,
+                }
+            },
+            Some(_) => return false,
+            None => Some(self.pc),
+        };
+        next_pc == Some(self.program.len())
+    }
+
+    /// The instruction occupying the current cycle, if any.
+    pub fn current_instruction(&self) -> Option<Instruction> {
+        self.current.map(|(instruction, _)| instruction)
+    }
+
+    /// What the CPU is doing this cycle. None before the first cycle and after
+    /// the program ends.
+    pub fn snapshot(&self) -> Option<TraceEntry> {
+        self.current_instruction().map(|instruction| TraceEntry {
+            cycle: self.cycle,
+            instruction,
+            registers: self.registers,
+        })
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) {
+        self.breakpoints.push(breakpoint);
+    }
+
+    pub fn remove_breakpoint(&mut self, breakpoint: &Breakpoint) {
+        self.breakpoints.retain(|b| b != breakpoint);
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    /// Run a single cycle, then report whether a breakpoint was hit. When
+    /// several match, the first one added wins.
+    pub fn step(&mut self) -> StopReason {
+        let state = self.execute_clock_cycle();
+        if state != RunState::Running {
+            return StopReason::Stopped(state)
+        }
+        match self.breakpoints.iter().find(|breakpoint| breakpoint.is_hit(self)) {
+            Some(&breakpoint) => StopReason::Breakpoint(breakpoint),
+            None => StopReason::Step,
+        }
+    }
+
+    /// Keep stepping until a breakpoint is hit or the program ends, which it
+    /// always does eventually thanks to the cycle limit.
+    pub fn resume(&mut self) -> StopReason {
+        loop {
+            match self.step() {
+                StopReason::Step => continue,
+                stop => return stop,
+            }
+        }
+    }
+
+    /// Start recording a `TraceEntry` for every cycle from here on.
+    pub fn enable_trace(&mut self) {
+        self.trace.get_or_insert_with(TraceRecorder::new);
+    }
+
+    /// Stop recording and throw away what was recorded.
+    pub fn disable_trace(&mut self) {
+        self.trace = None;
+    }
+
+    pub fn trace(&self) -> &[TraceEntry] {
+        self.trace.as_ref().map_or(&[], TraceRecorder::entries)
+    }
+
+    /// Write the trace to a file, one cycle per line.
+    pub fn dump_trace(&self, file_path: &str) -> io::Result<()> {
+        match &self.trace {
+            Some(trace) => trace.write(file_path),
+            None => TraceRecorder::new().write(file_path),
+        }
+    }
+
+    pub fn get_signal_strength(&self) -> isize {
+        self.cycle as isize * self.register(Register::X)
+    }
+}
+
+impl Default for CPU {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// What a peripheral has to show for itself after a run.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PeripheralOutput {
+    SignalStrength(isize),
+    Screen(String),
+    Trace(Vec<TraceEntry>),
+}
+
+/// Something plugged into the comms device that watches the CPU every cycle.
+pub trait Peripheral {
+    /// Called during each cycle, after the CPU has started it.
+    fn on_cycle(&mut self, cpu: &CPU);
+
+    fn output(&self) -> PeripheralOutput;
+}
+
+impl Peripheral for CRT {
+    fn on_cycle(&mut self, cpu: &CPU) {
+        self.lazer_beam_it(cpu.cycle, cpu.register(Register::X));
+    }
+
+    fn output(&self) -> PeripheralOutput {
+        PeripheralOutput::Screen(self.render())
+    }
+}
+
+/// Sums the signal strength during the cycles of interest.
+pub struct SignalSampler {
+    cycles: Vec<usize>,
+    total: isize,
+}
+
+impl SignalSampler {
+    pub fn new(cycles: Vec<usize>) -> Self {
+        Self {
+            cycles,
+            total: 0,
+        }
+    }
+}
+
+impl Default for SignalSampler {
+    fn default() -> Self {
+        Self::new(vec![20, 60, 100, 140, 180, 220])
+    }
+}
+
+impl Peripheral for SignalSampler {
+    fn on_cycle(&mut self, cpu: &CPU) {
+        if self.cycles.contains(&cpu.cycle) {
+            self.total += cpu.get_signal_strength();
+        }
+    }
+
+    fn output(&self) -> PeripheralOutput {
+        PeripheralOutput::SignalStrength(self.total)
+    }
+}
+
+/// Records what the CPU was doing every cycle. Plug one into a `Device`, or
+/// see `CPU::enable_trace`, which keeps one inside the CPU.
+#[derive(Clone, Debug, Default)]
+pub struct TraceRecorder {
+    entries: Vec<TraceEntry>,
+}
+
+impl TraceRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Note down what the CPU is doing this cycle, if anything.
+    pub fn record(&mut self, cpu: &CPU) {
+        if let Some(entry) = cpu.snapshot() {
+            self.entries.push(entry);
+        }
+    }
+
+    pub fn entries(&self) -> &[TraceEntry] {
+        &self.entries
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Write the entries to a file, one cycle per line.
+    pub fn write(&self, file_path: &str) -> io::Result<()> {
+        let mut file = io::BufWriter::new(fs::File::create(file_path)?);
+        writeln!(file, "{:>6}  {:<12} registers", "cycle", "instruction")?;
+        for entry in &self.entries {
+            writeln!(file, "{entry}")?;
+        }
+        file.flush()
+    }
+}
+
+impl Peripheral for TraceRecorder {
+    fn on_cycle(&mut self, cpu: &CPU) {
+        self.record(cpu);
+    }
+
+    fn output(&self) -> PeripheralOutput {
+        PeripheralOutput::Trace(self.entries.clone())
+    }
+}
+
+/// The whole comms device: a CPU and its CRT running off one clock, plus
+/// whatever other peripherals are plugged in.
+pub struct Device {
+    pub cpu: CPU,
+    pub crt: CRT,
+    peripherals: Vec<Box<dyn Peripheral>>,
+}
+
+impl Device {
+    pub fn new(cpu: CPU, crt: CRT) -> Self {
+        Self {
+            cpu,
+            crt,
+            peripherals: Vec::new(),
+        }
+    }
+
+    pub fn plug_in(&mut self, peripheral: Box<dyn Peripheral>) {
+        self.peripherals.push(peripheral);
+    }
+
+    /// Run one clock cycle on everything. Returns false once the CPU stops
+    /// (halted or faulted), in which case nothing else is ticked.
+    pub fn tick(&mut self) -> bool {
+        if self.cpu.execute_clock_cycle() != RunState::Running {
+            return false
+        }
+        self.crt.on_cycle(&self.cpu);
+        for peripheral in self.peripherals.iter_mut() {
+            peripheral.on_cycle(&self.cpu);
+        }
+        true
+    }
+
+    /// Run the program to the end (or the CPU's cycle limit). Returns the
+    /// CRT's output followed by each peripheral's, in the order they were
+    /// plugged in.
+    pub fn run(&mut self) -> Vec<PeripheralOutput> {
+        while self.tick() {}
+        let mut outputs = vec![self.crt.output()];
+        outputs.extend(self.peripherals.iter().map(|peripheral| peripheral.output()));
+        outputs
+    }
+}
+
+/// How a rope end chases the end ahead of it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FollowRule {
+    /// The puzzle's rule: once no longer touching (diagonals count), step one
+    /// space towards the leader in x and in y.
+    #[default]
+    Taut,
+    /// Only ever step orthogonally, and only sharing an edge counts as
+    /// touching. Closes the bigger gap first (x on a tie).
+    Orthogonal,
+    /// Like `Taut`, but happy to hang back up to this many spaces.
+    Slack(usize),
+    /// Walk exactly the path the leader took, this many leader moves behind.
+    Lagged(usize),
+}
+
+/// Rope end structure to track position on a grid.
+///
+/// Initial position is 0, 0.
+#[derive(Clone)]
+pub struct RopeEnd {
+    pub x: isize,
     pub y: isize,
-    pub visited_spaces: HashSet<[isize; 2]>,  // Keep track of where it's been.
+    pub visited_spaces: Grid<bool>,  // Keep track of where it's been.
+    pub rule: FollowRule,
+    leader_path: VecDeque<[isize; 2]>,  // Where the leader has been, for Lagged.
+}
+
+impl RopeEnd {
+    /// Create a new rope end at the starting position.
+    pub fn new() -> Self {
+        Self::with_rule(FollowRule::default())
+    }
+
+    /// Create a new rope end at the starting position that follows by `rule`.
+    pub fn with_rule(rule: FollowRule) -> Self {
+        let mut visited_spaces: Grid<bool> = Grid::infinite(false);
+        visited_spaces.set([0, 0], true);
+        Self {
+            x: 0,
+            y: 0,
+            visited_spaces,
+            rule,
+            leader_path: VecDeque::new(),
+        }
+    }
+
+    /// Make a delta move in x and y.
+    ///
+    /// e.g. move_delta(1, 1) would increase x and y each by 1.
+    pub fn move_delta(&mut self, deltas: [isize; 2]) {
+        self.x += deltas[0];
+        self.y += deltas[1];
+        self.visited_spaces.set([self.x, self.y], true);
+    }
+
+    /// Number of distinct spaces this end has been to, including the start.
+    pub fn n_visited(&self) -> usize {
+        self.visited_spaces.count(|&visited| visited)
+    }
+
+    /// Jump straight to a space.
+    pub fn move_to(&mut self, [x, y]: [isize; 2]) {
+        self.move_delta([x - self.x, y - self.y]);
+    }
+
+    pub fn follow(&mut self, leader: &Self) {
+        let dx = leader.x - self.x ;
+        let dy = leader.y - self.y ;
+        match self.rule {
+            FollowRule::Taut => self.follow_with_slack(dx, dy, 1),
+            FollowRule::Slack(slack) => self.follow_with_slack(dx, dy, slack as isize),
+            FollowRule::Orthogonal => {
+                if dx.abs() + dy.abs() > 1 {
+                    if dx.abs() >= dy.abs() {
+                        self.move_delta([dx.signum(), 0])
+                    } else {
+                        self.move_delta([0, dy.signum()])
+                    }
+                }
+            },
+            FollowRule::Lagged(lag) => {
+                // Only log the leader when it has actually gone somewhere.
+                let last_seen = self.leader_path.back().copied().unwrap_or([self.x, self.y]);
+                if last_seen != [leader.x, leader.y] {
+                    self.leader_path.push_back([leader.x, leader.y]);
+                }
+                while self.leader_path.len() > lag {
+                    let next = self.leader_path.pop_front().unwrap();
+                    self.move_to(next);
+                }
+            },
+        }
+    }
+
+    fn follow_with_slack(&mut self, dx: isize, dy: isize, slack: isize) {
+        if (dx.abs() > slack) || (dy.abs() > slack) {
+            // Only ever move one space in each direction.
+            self.move_delta([dx.signum(), dy.signum()])
+        }
+    }
+}
+
+impl Default for RopeEnd {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Which way the head of a rope moves. Up is +y.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+    UpLeft,
+    UpRight,
+    DownLeft,
+    DownRight,
+}
+
+impl Direction {
+    pub fn delta(self) -> [isize; 2] {
+        match self {
+            Self::Up => [0, 1],
+            Self::Down => [0, -1],
+            Self::Left => [-1, 0],
+            Self::Right => [1, 0],
+            Self::UpLeft => [-1, 1],
+            Self::UpRight => [1, 1],
+            Self::DownLeft => [-1, -1],
+            Self::DownRight => [1, -1],
+        }
+    }
+
+    /// `U`, `D`, `L`, `R`, or two of them for a diagonal in either order
+    /// (`UL` or `LU`).
+    fn parse(source: &str) -> Option<Self> {
+        match source {
+            "U" => Some(Self::Up),
+            "D" => Some(Self::Down),
+            "L" => Some(Self::Left),
+            "R" => Some(Self::Right),
+            "UL" | "LU" => Some(Self::UpLeft),
+            "UR" | "RU" => Some(Self::UpRight),
+            "DL" | "LD" => Some(Self::DownLeft),
+            "DR" | "RD" => Some(Self::DownRight),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let label = match self {
+            Self::Up => "U",
+            Self::Down => "D",
+            Self::Left => "L",
+            Self::Right => "R",
+            Self::UpLeft => "UL",
+            Self::UpRight => "UR",
+            Self::DownLeft => "DL",
+            Self::DownRight => "DR",
+        };
+        write!(f, "{label}")
+    }
+}
+
+/// One instruction for the head of a rope, e.g. `R 4`: take `steps` steps in
+/// `direction`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RopeMove {
+    pub direction: Direction,
+    pub steps: usize,
+}
+
+impl fmt::Display for RopeMove {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.direction, self.steps)
+    }
+}
+
+/// Why a list of rope moves didn't parse. Line numbers start at 1.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RopeMoveError {
+    UnknownDirection { line: usize, direction: String },
+    MissingStepCount { line: usize },
+    BadStepCount { line: usize, steps: String },
+}
+
+impl fmt::Display for RopeMoveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::UnknownDirection { line, direction } => {
+                write!(f, "line {line}: unknown direction `{direction}`")
+            },
+            Self::MissingStepCount { line } => write!(f, "line {line}: missing step count"),
+            Self::BadStepCount { line, steps } => {
+                write!(f, "line {line}: bad step count `{steps}`")
+            },
+        }
+    }
+}
+
+impl std::error::Error for RopeMoveError {}
+
+/// Parse rope moves.
+///
+/// Each move is a direction (see `Direction`) and a step count, e.g. `R 4`,
+/// `UL 2` or `D3`. A line can hold several moves separated by commas
+/// (`R 4, U 2`), and anything after a `#` is a comment. Blank lines are fine.
+pub fn parse_rope_moves(moves: &str) -> Result<Vec<RopeMove>, RopeMoveError> {
+    let mut parsed: Vec<RopeMove> = Vec::new();
+    for (idx, line) in moves.split('\n').enumerate() {
+        let line_number = idx + 1;
+        let code = line.split_once('#').map_or(line, |(code, _)| code);
+        for move_ in code.split(',').map(str::trim).filter(|move_| !move_.is_empty()) {
+            // The direction is everything up to the step count.
+            let split_at = move_
+                .find(|c: char| c.is_ascii_digit() || c.is_whitespace())
+                .unwrap_or(move_.len());
+            let (direction, steps) = move_.split_at(split_at);
+            let direction = Direction::parse(direction).ok_or(RopeMoveError::UnknownDirection {
+                line: line_number,
+                direction: direction.to_string(),
+            })?;
+            let steps = steps.trim();
+            if steps.is_empty() {
+                return Err(RopeMoveError::MissingStepCount { line: line_number })
+            }
+            let steps = steps.parse::<usize>().map_err(|_| RopeMoveError::BadStepCount {
+                line: line_number,
+                steps: steps.to_string(),
+            })?;
+            parsed.push(RopeMove { direction, steps });
+        }
+    }
+    Ok(parsed)
+}
+
+/// A rope made of knots, head first. Each knot follows the one before it.
+#[derive(Clone)]
+pub struct Rope {
+    knots: Vec<RopeEnd>,
+}
+
+impl Rope {
+    /// A rope of `n_knots` knots, all piled up at the start.
+    pub fn new(n_knots: usize) -> Self {
+        Self::with_rule(n_knots, FollowRule::default())
+    }
+
+    /// A rope where every knot follows by the same rule.
+    pub fn with_rule(n_knots: usize, rule: FollowRule) -> Self {
+        if n_knots == 0 {
+            panic!("A rope needs at least one knot.");
+        }
+        Self {
+            knots: (0..n_knots).map(|_| RopeEnd::with_rule(rule)).collect(),
+        }
+    }
+
+    /// Change how one knot follows the knot ahead of it. The head doesn't
+    /// follow anything, so its rule does nothing.
+    pub fn set_rule(&mut self, knot_idx: usize, rule: FollowRule) {
+        self.knots[knot_idx].rule = rule;
+    }
+
+    pub fn n_knots(&self) -> usize {
+        self.knots.len()
+    }
+
+    /// Knot 0 is the head, knot `n_knots() - 1` is the tail.
+    pub fn knot(&self, knot_idx: usize) -> &RopeEnd {
+        &self.knots[knot_idx]
+    }
+
+    pub fn head(&self) -> &RopeEnd {
+        &self.knots[0]
+    }
+
+    pub fn tail(&self) -> &RopeEnd {
+        &self.knots[self.knots.len()-1]
+    }
+
+    /// Move the head a single step and let the rest of the rope catch up.
+    pub fn step(&mut self, delta: [isize; 2]) {
+        self.knots[0].move_delta(delta);
+        for knot_idx in 1..self.knots.len() {
+            // Split so the leader can be borrowed while the follower moves.
+            let (leaders, followers) = self.knots.split_at_mut(knot_idx);
+            followers[0].follow(&leaders[knot_idx-1]);
+        }
+    }
+
+    pub fn apply_move(&mut self, move_: &RopeMove) {
+        for _ in 0..move_.steps {
+            self.step(move_.direction.delta());
+        }
+    }
+
+    pub fn apply_moves(&mut self, moves: &[RopeMove]) {
+        for move_ in moves {
+            self.apply_move(move_);
+        }
+    }
+
+    /// Every space a knot has been to.
+    pub fn visited(&self, knot_idx: usize) -> &Grid<bool> {
+        &self.knots[knot_idx].visited_spaces
+    }
+
+    /// Number of distinct spaces a knot has been to, including the start.
+    pub fn n_visited(&self, knot_idx: usize) -> usize {
+        self.knots[knot_idx].n_visited()
+    }
+
+    /// How a knot is labelled in the puzzle's diagrams: `H` for the head, `T`
+    /// for the tail of a 2-knot rope, otherwise its number. Knots past 9 are
+    /// all `*`.
+    fn label(&self, knot_idx: usize) -> char {
+        match knot_idx {
+            0 => 'H',
+            1 if self.knots.len() == 2 => 'T',
+            1..=9 => char::from_digit(knot_idx as u32, 10).unwrap(),
+            _ => '*',
+        }
+    }
+
+    /// Smallest window ([[min x, min y], [max x, max y]]) holding the start,
+    /// every knot and everywhere any knot has been.
+    pub fn extent(&self) -> [[isize; 2]; 2] {
+        let mut window = [[0, 0], [0, 0]];
+        for knot in &self.knots {
+            let visited_corners = knot.visited_spaces.bounds().map_or(Vec::new(), Vec::from);
+            for [x, y] in visited_corners.into_iter().chain([[knot.x, knot.y]]) {
+                window = [
+                    [window[0][0].min(x), window[0][1].min(y)],
+                    [window[1][0].max(x), window[1][1].max(y)],
+                ];
+            }
+        }
+        window
+    }
+
+    /// Draw the rope like the puzzle does, over `[[min x, min y], [max x,
+    /// max y]]`. Up is +y, so the top line is `max y`.
+    ///
+    /// Knots closer to the head are drawn on top. `s` marks the start; with
+    /// `show_trail`, `#` marks everywhere the tail has been.
+    pub fn render_window(&self, window: [[isize; 2]; 2], show_trail: bool) -> String {
+        self.draw(window, true, show_trail)
+    }
+
+    /// Just the start and the tail's trail, like the puzzle's final diagrams.
+    pub fn render_trail(&self, window: [[isize; 2]; 2]) -> String {
+        self.draw(window, false, true)
+    }
+
+    fn draw(&self, [[x_min, y_min], [x_max, y_max]]: [[isize; 2]; 2], show_knots: bool, show_trail: bool) -> String {
+        let mut picture = String::new();
+        for y in (y_min..=y_max).rev() {
+            for x in x_min..=x_max {
+                let knot = self.knots
+                    .iter()
+                    .position(|knot| show_knots && [knot.x, knot.y] == [x, y]);
+                picture.push(match knot {
+                    Some(knot_idx) => self.label(knot_idx),
+                    None if [x, y] == [0, 0] => 's',
+                    None if show_trail && self.tail().visited_spaces[[x, y]] => '#',
+                    None => '.',
+                });
+            }
+            picture.push('\n');
+        }
+        picture
+    }
+
+    /// `render_window` over the rope's `extent`.
+    pub fn render(&self, show_trail: bool) -> String {
+        self.render_window(self.extent(), show_trail)
+    }
+
+    /// Apply the moves while printing the rope after each one (or after every
+    /// single step), puzzle style. The window is sized up front to fit the
+    /// whole journey so the frames don't jump around.
+    pub fn playback(&mut self, moves: &[RopeMove], every_step: bool) {
+        let mut rehearsal = self.clone();
+        rehearsal.apply_moves(moves);
+        let window = rehearsal.extent();
+
+        println!("== Initial State ==\n");
+        println!("{}", self.render_window(window, false));
+        for move_ in moves {
+            println!("== {move_} ==\n");
+            for _ in 0..move_.steps {
+                self.step(move_.direction.delta());
+                if every_step {
+                    println!("{}", self.render_window(window, false));
+                }
+            }
+            if !every_step {
+                println!("{}", self.render_window(window, false));
+            }
+        }
+        println!("== Visited by the tail ==\n");
+        println!("{}", self.render_trail(window));
+    }
 }
 
-impl RopeEnd {
-    /// Create a new rope end at the starting position.
-    pub fn new() -> Self {
-        let mut visited_spaces: HashSet<[isize; 2]> = HashSet::new();
-        visited_spaces.insert([0, 0]);
-        Self {
-            x: 0,
-            y: 0,
-            visited_spaces,
+/// Day 8: a forest of trees, one digit of height per tree.
+///
+/// A tree can see (and be seen from) the edge of the forest along a direction
+/// if every tree between it and that edge is shorter. Its viewing distance is
+/// how many trees it can see before one at least as tall blocks the view
+/// (that tree included).
+pub struct Forest {
+    heights: Grid<i8>,
+}
+
+impl Forest {
+    pub fn new(heights: Grid<i8>) -> Self {
+        Self {
+            heights,
+        }
+    }
+
+    /// Parse a map of digits. Every row has to be the same width; an empty
+    /// map makes an empty forest.
+    pub fn parse(map: &str) -> Result<Self, GridError> {
+        let heights = Grid::try_from_char_map(map, |digit| {
+            digit.to_digit(10).map(|height| height as i8)
+        })?;
+        Ok(Self::new(heights))
+    }
+
+    pub fn heights(&self) -> &Grid<i8> {
+        &self.heights
+    }
+
+    /// Each row or column as a viewer looking along `step` would meet them,
+    /// walked backwards: starting at the edge `step` points at.
+    fn lines_towards(&self, step: [isize; 2]) -> Vec<Vec<[isize; 2]>> {
+        let (width, height) = (self.heights.width() as isize, self.heights.height() as isize);
+        let starts: Vec<[isize; 2]> = match step {
+            [1, 0] => (0..height).map(|y| [width-1, y]).collect(),
+            [-1, 0] => (0..height).map(|y| [0, y]).collect(),
+            [0, 1] => (0..width).map(|x| [x, height-1]).collect(),
+            [0, -1] => (0..width).map(|x| [x, 0]).collect(),
+            _ => panic!("{step:?} isn't one of the four cardinal directions."),
+        };
+        starts
+            .into_iter()
+            .map(|start| {
+                let mut line = vec![start];
+                line.extend(self.heights.ray(start, [-step[0], -step[1]]).map(|(coords, _)| coords));
+                line
+            })
+            .collect()
+    }
+
+    /// Look along `step` from every tree. Returns whether each tree can see the
+    /// edge that way, and how far it can see.
+    ///
+    /// Linear time: walking in from that edge, a stack holds the trees that
+    /// could still block the view of trees further in. Anything shorter than
+    /// the current tree is hidden behind it from here on, so it gets popped;
+    /// whatever is left on top is the nearest tree at least as tall.
+    fn scan(&self, step: [isize; 2]) -> (Grid<bool>, Grid<usize>) {
+        let (width, height) = (self.heights.width(), self.heights.height());
+        let mut can_see_edge: Grid<bool> = Grid::bounded(width, height, false);
+        let mut viewing_distance: Grid<usize> = Grid::bounded(width, height, 0);
+        for line in self.lines_towards(step) {
+            // (position along the line, height)
+            let mut blockers: Vec<(usize, i8)> = Vec::new();
+            for (position, &coords) in line.iter().enumerate() {
+                let tree_height = self.heights[coords];
+                while blockers.last().is_some_and(|&(_, h)| h < tree_height) {
+                    blockers.pop();
+                }
+                match blockers.last() {
+                    Some(&(blocker, _)) => viewing_distance[coords] = position - blocker,
+                    None => {
+                        can_see_edge[coords] = true;
+                        viewing_distance[coords] = position;
+                    },
+                }
+                blockers.push((position, tree_height));
+            }
+        }
+        (can_see_edge, viewing_distance)
+    }
+
+    /// Which trees can be seen from outside the forest looking back along
+    /// `step` (one of `NEIGHBORS_4`).
+    pub fn visible_towards(&self, step: [isize; 2]) -> Grid<bool> {
+        self.scan(step).0
+    }
+
+    /// How many trees each tree can see looking along `step` (one of
+    /// `NEIGHBORS_4`).
+    pub fn viewing_distances(&self, step: [isize; 2]) -> Grid<usize> {
+        self.scan(step).1
+    }
+
+    /// Which trees can be seen from outside the forest in any direction.
+    pub fn visibility(&self) -> Grid<bool> {
+        let mut is_visible: Grid<bool> = Grid::bounded(self.heights.width(), self.heights.height(), false);
+        for step in NEIGHBORS_4 {
+            for (coords, &visible) in self.visible_towards(step).iter() {
+                is_visible[coords] |= visible;
+            }
+        }
+        is_visible
+    }
+
+    /// Product of the viewing distances in all four directions.
+    pub fn scenic_scores(&self) -> Grid<usize> {
+        let mut scenic_score: Grid<usize> = Grid::bounded(self.heights.width(), self.heights.height(), 1);
+        for step in NEIGHBORS_4 {
+            for (coords, &distance) in self.viewing_distances(step).iter() {
+                scenic_score[coords] *= distance;
+            }
+        }
+        scenic_score
+    }
+
+    pub fn n_visible(&self) -> usize {
+        self.visibility().count(|&visible| visible)
+    }
+
+    /// None for a forest with no trees.
+    pub fn best_scenic_score(&self) -> Option<usize> {
+        self.scenic_scores().iter().map(|(_, &score)| score).max()
+    }
+
+    /// Trees seen from `viewpoint` (a spot in the forest) with eyes at
+    /// `eye_height`, looking out along each of `steps`.
+    ///
+    /// Along a line of sight every tree shorter than eye level can be seen
+    /// over; the first tree at least that tall is seen and hides the rest,
+    /// same as the viewing distance rule. Steps don't have to be unit steps:
+    /// see `slope_step` for lines at other angles, which only pass over the
+    /// trees they land on exactly.
+    pub fn visible_from(&self, viewpoint: [isize; 2], eye_height: i8, steps: &[[isize; 2]]) -> HashSet<[isize; 2]> {
+        let mut visible: HashSet<[isize; 2]> = HashSet::new();
+        for &step in steps {
+            for (coords, &tree_height) in self.heights.ray(viewpoint, step) {
+                visible.insert(coords);
+                if tree_height >= eye_height {
+                    break
+                }
+            }
+        }
+        visible
+    }
+
+    /// `visible_from` looking along all eight compass directions.
+    pub fn visible_all_around(&self, viewpoint: [isize; 2], eye_height: i8) -> HashSet<[isize; 2]> {
+        self.visible_from(viewpoint, eye_height, &NEIGHBORS_8)
+    }
+
+    /// Scenic scores as a plain (P2) PGM image, brightest for the best spot.
+    pub fn scenic_heatmap_pgm(&self) -> String {
+        let scores = self.scenic_scores();
+        let best = self.best_scenic_score().unwrap_or(0).max(1);
+        let mut image = format!("P2\n{} {}\n255\n", scores.width(), scores.height());
+        for y in 0..scores.height() as isize {
+            let row: Vec<String> = scores
+                .row(y)
+                .map(|&score| (score * 255 / best).to_string())
+                .collect();
+            image.push_str(&row.join(" "));
+            image.push('\n');
+        }
+        image
+    }
+
+    pub fn write_scenic_heatmap(&self, file_path: &str) -> io::Result<()> {
+        fs::write(file_path, self.scenic_heatmap_pgm())
+    }
+
+    /// Scenic scores shaded with ASCII, from ` ` (0) to `@` (the best).
+    pub fn render_scenic_heatmap(&self) -> String {
+        const SHADES: &[u8] = b" .:-=+*#%@";
+        let best = self.best_scenic_score().unwrap_or(0).max(1);
+        self.scenic_scores().render(|_, &score| {
+            SHADES[score * (SHADES.len() - 1) / best] as char
+        })
+    }
+}
+
+/// The smallest whole step along a line of sight moving `dx` across for every
+/// `dy` down, e.g. (4, -2) -> [2, -1]. Both zero is no direction at all.
+pub fn slope_step(dx: isize, dy: isize) -> [isize; 2] {
+    if dx == 0 && dy == 0 {
+        panic!("A slope needs to go somewhere.");
+    }
+    // Euclid.
+    let (mut a, mut b) = (dx.abs(), dy.abs());
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    [dx / a, dy / a]
+}
+
+/// Day 7: the comms device's filesystem, pieced back together from a terminal
+/// transcript.
+///
+/// Nodes live in one Vec and refer to each other by index. Node 0 (`ROOT`) is
+/// `/`.
+#[derive(Clone, Debug)]
+pub struct FsTree {
+    nodes: Vec<FsNode>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FsNodeKind {
+    Dir,
+    File { size: usize },
+}
+
+#[derive(Clone, Debug)]
+pub struct FsNode {
+    pub name: String,
+    pub kind: FsNodeKind,
+    pub parent: Option<usize>,
+    pub children: Vec<usize>,
+}
+
+impl FsNode {
+    pub fn is_dir(&self) -> bool {
+        self.kind == FsNodeKind::Dir
+    }
+}
+
+impl FsTree {
+    pub const ROOT: usize = 0;
+
+    /// A filesystem with nothing but `/`.
+    pub fn new() -> Self {
+        Self {
+            nodes: vec![FsNode {
+                name: "/".to_string(),
+                kind: FsNodeKind::Dir,
+                parent: None,
+                children: Vec::new(),
+            }],
+        }
+    }
+
+    /// Rebuild the filesystem from a `$ cd`/`$ ls` transcript, ignoring
+    /// anything `parse_transcript` would warn about.
+    pub fn from_transcript(transcript: &str) -> Self {
+        Self::parse_transcript(transcript).0
+    }
+
+    /// Rebuild the filesystem from a `$ cd`/`$ ls` transcript.
+    ///
+    /// Every line starting with `$` is a command; anything else is output
+    /// from the last `ls`. `cd` takes absolute or relative paths with any
+    /// number of segments (`cd ../b/c`), and `cd ..` at `/` stays put, like a
+    /// real shell. Everything after `cd ` is the path, so names can have
+    /// spaces in them (though not at the end, since lines get trimmed).
+    /// Listing the same directory twice doesn't count anything twice. Lines
+    /// that can't be made sense of are skipped and reported.
+    pub fn parse_transcript(transcript: &str) -> (Self, Vec<TranscriptWarning>) {
+        let mut tree = Self::new();
+        let mut warnings: Vec<TranscriptWarning> = Vec::new();
+        let mut cwd = Self::ROOT;
+        let mut is_listing = false;
+        for (idx, text) in transcript.split('\n').map(str::trim).enumerate() {
+            let line = idx + 1;
+            if text.is_empty() {
+                continue
+            }
+            if let Some(cmd) = text.strip_prefix('$') {
+                is_listing = false;
+                if cmd.trim() == "ls" {
+                    is_listing = true;
+                    continue
+                }
+                // Everything after `cd ` is the path, spaces and all.
+                match cmd.trim().split_once(' ') {
+                    Some(("cd", path)) => match tree.resolve_dir(cwd, path) {
+                        Ok(dir) => cwd = dir,
+                        Err(file) => warnings.push(TranscriptWarning::KindConflict {
+                            line,
+                            path: tree.path(file),
+                        }),
+                    },
+                    _ => warnings.push(TranscriptWarning::UnknownCommand {
+                        line,
+                        command: cmd.trim().to_string(),
+                    }),
+                }
+                continue
+            }
+            if !is_listing {
+                warnings.push(TranscriptWarning::UnexpectedOutput {
+                    line,
+                    output: text.to_string(),
+                });
+                continue
+            }
+            // Output of `ls`.
+            let entry = match text.split_once(' ') {
+                Some(("dir", name)) => Some((name, FsNodeKind::Dir)),
+                Some((size, name)) => size
+                    .parse::<usize>()
+                    .ok()
+                    .map(|size| (name, FsNodeKind::File { size })),
+                None => None,
+            };
+            let Some((name, kind)) = entry.filter(|(name, _)| is_entry_name(name)) else {
+                warnings.push(TranscriptWarning::MalformedOutput {
+                    line,
+                    output: text.to_string(),
+                });
+                continue
+            };
+            match tree.entry(cwd, name, &kind) {
+                Ok(id) => {
+                    if let (FsNodeKind::File { size: old }, FsNodeKind::File { size: new }) = (&tree.nodes[id].kind, &kind) {
+                        if old != new {
+                            warnings.push(TranscriptWarning::SizeChanged {
+                                line,
+                                path: tree.path(id),
+                                old: *old,
+                                new: *new,
+                            });
+                            tree.nodes[id].kind = kind;
+                        }
+                    }
+                },
+                Err(existing) => warnings.push(TranscriptWarning::KindConflict {
+                    line,
+                    path: tree.path(existing),
+                }),
+            }
+        }
+        (tree, warnings)
+    }
+
+    /// Follow a `cd` path from `cwd`, creating any directories that were never
+    /// listed (they must be there if we could cd into them). Errs with the
+    /// node that turned out to be a file.
+    fn resolve_dir(&mut self, cwd: usize, path: &str) -> Result<usize, usize> {
+        let mut dir = if path.starts_with('/') { Self::ROOT } else { cwd };
+        for name in path.split('/').filter(|name| !name.is_empty()) {
+            dir = match name {
+                "." => dir,
+                ".." => self.parent(dir).unwrap_or(Self::ROOT),
+                _ => self.entry(dir, name, &FsNodeKind::Dir)?,
+            };
+        }
+        Ok(dir)
+    }
+
+    /// The entry called `name` in `dir`, added if it isn't there yet. Errs
+    /// with the existing node if it's a file where a directory was wanted or
+    /// vice versa. An existing file's size is left alone.
+    fn entry(&mut self, dir: usize, name: &str, kind: &FsNodeKind) -> Result<usize, usize> {
+        match self.child(dir, name) {
+            Some(existing) if self.nodes[existing].is_dir() == (*kind == FsNodeKind::Dir) => Ok(existing),
+            Some(existing) => Err(existing),
+            None => Ok(self.add_node(dir, name, kind.clone())),
+        }
+    }
+
+    fn add_node(&mut self, parent: usize, name: &str, kind: FsNodeKind) -> usize {
+        if !self.nodes[parent].is_dir() {
+            panic!("{} isn't a directory.", self.path(parent));
+        }
+        if !is_entry_name(name) {
+            panic!("`{name}` can't be a file or directory name.");
+        }
+        let id = self.nodes.len();
+        self.nodes.push(FsNode {
+            name: name.to_string(),
+            kind,
+            parent: Some(parent),
+            children: Vec::new(),
+        });
+        self.nodes[parent].children.push(id);
+        id
+    }
+
+    /// Add a directory, or find the one that's already there. Panics if
+    /// there's a file by that name, or if the name is empty, `.`, `..` or
+    /// has a `/` in it.
+    pub fn add_dir(&mut self, parent: usize, name: &str) -> usize {
+        self.entry(parent, name, &FsNodeKind::Dir)
+            .unwrap_or_else(|file| panic!("{} is a file.", self.path(file)))
+    }
+
+    /// Add a file, or update the size of the one that's already there.
+    /// Panics if there's a directory by that name, or the name is no good
+    /// (see `add_dir`).
+    pub fn add_file(&mut self, parent: usize, name: &str, size: usize) -> usize {
+        let kind = FsNodeKind::File { size };
+        let id = self.entry(parent, name, &kind)
+            .unwrap_or_else(|dir| panic!("{} is a directory.", self.path(dir)));
+        self.nodes[id].kind = kind;
+        id
+    }
+
+    pub fn node(&self, id: usize) -> &FsNode {
+        &self.nodes[id]
+    }
+
+    /// Number of nodes, `/` included.
+    pub fn n_nodes(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn parent(&self, id: usize) -> Option<usize> {
+        self.nodes[id].parent
+    }
+
+    /// The entry called `name` directly inside `dir`.
+    pub fn child(&self, dir: usize, name: &str) -> Option<usize> {
+        self.nodes[dir]
+            .children
+            .iter()
+            .copied()
+            .find(|&child| self.nodes[child].name == name)
+    }
+
+    /// Parent, grandparent, ... up to and including `/`.
+    pub fn ancestors(&self, id: usize) -> impl Iterator<Item = usize> + '_ {
+        std::iter::successors(self.parent(id), |&ancestor| self.parent(ancestor))
+    }
+
+    /// Absolute path, e.g. `/a/e/i`.
+    pub fn path(&self, id: usize) -> String {
+        if id == Self::ROOT {
+            return "/".to_string()
         }
+        let mut names: Vec<&str> = vec![&self.nodes[id].name];
+        names.extend(
+            self.ancestors(id)
+                .filter(|&ancestor| ancestor != Self::ROOT)
+                .map(|ancestor| self.nodes[ancestor].name.as_str())
+        );
+        names.reverse();
+        format!("/{}", names.join("/"))
     }
 
-    /// Make a delta move in x and y.
+    /// Find a node by absolute path (a trailing `/` is fine).
+    pub fn lookup(&self, path: &str) -> Option<usize> {
+        let mut id = Self::ROOT;
+        for name in path.split('/').filter(|name| !name.is_empty()) {
+            id = self.child(id, name)?;
+        }
+        Some(id)
+    }
+
+    /// Size of a file, or total size of everything under a directory.
+    pub fn size(&self, id: usize) -> usize {
+        match self.nodes[id].kind {
+            FsNodeKind::File { size } => size,
+            FsNodeKind::Dir => self.nodes[id].children.iter().map(|&child| self.size(child)).sum(),
+        }
+    }
+
+    /// Total size of every directory, indexed by node (0 for files). One pass,
+    /// rather than a `size` call per directory.
+    pub fn dir_sizes(&self) -> Vec<usize> {
+        let mut totals: Vec<usize> = vec![0; self.nodes.len()];
+        // Children always come after their parents, so summing backwards
+        // finishes every directory before its parent needs it.
+        for id in (0..self.nodes.len()).rev() {
+            if let FsNodeKind::File { size } = self.nodes[id].kind {
+                if let Some(parent) = self.nodes[id].parent {
+                    totals[parent] += size;
+                }
+            } else if let Some(parent) = self.nodes[id].parent {
+                totals[parent] += totals[id];
+            }
+        }
+        totals
+    }
+
+    /// Depth-first walk over everything under `from` (itself included),
+    /// yielding (node, depth below `from`).
+    pub fn walk(&self, from: usize) -> FsWalk<'_> {
+        FsWalk {
+            tree: self,
+            stack: vec![(from, 0)],
+        }
+    }
+
+    /// Every directory, `/` first, depth first.
+    pub fn dirs(&self) -> impl Iterator<Item = usize> + '_ {
+        self.walk(Self::ROOT).map(|(id, _)| id).filter(|&id| self.nodes[id].is_dir())
+    }
+
+    /// Every file, depth first.
+    pub fn files(&self) -> impl Iterator<Item = usize> + '_ {
+        self.walk(Self::ROOT).map(|(id, _)| id).filter(|&id| !self.nodes[id].is_dir())
+    }
+
+    /// The whole tree, puzzle style, with the total size of every directory:
     ///
-    /// e.g. move_delta(1, 1) would increase x and y each by 1.
-    pub fn move_delta(&mut self, deltas: [isize; 2]) {
-        self.x += deltas[0];
-        self.y += deltas[1];
-        self.visited_spaces.insert([self.x, self.y]);
+    /// ```text
+    /// - / (dir, size=48381165)
+    ///   - a (dir, size=94853)
+    ///     - e (dir, size=584)
+    ///       - i (file, size=584)
+    /// ```
+    pub fn render_tree(&self) -> String {
+        let dir_sizes = self.dir_sizes();
+        let mut picture = String::new();
+        for (id, depth) in self.walk(Self::ROOT) {
+            let node = &self.nodes[id];
+            let description = match node.kind {
+                FsNodeKind::Dir => format!("dir, size={}", dir_sizes[id]),
+                FsNodeKind::File { size } => format!("file, size={size}"),
+            };
+            picture.push_str(&format!("{}- {} ({description})\n", "  ".repeat(depth), node.name));
+        }
+        picture
     }
 
-    pub fn follow(&mut self, leader: &Self) {
-        let dx = leader.x - self.x ;
-        let dy = leader.y - self.y ;
-        if (dx.abs() > 1) || (dy.abs() > 1) {
-            // Only ever move one space in each direction.
-            self.move_delta([dx.signum(), dy.signum()])
+    /// Every directory's path and total size, biggest first (then by path),
+    /// like `du | sort -rn`.
+    pub fn du(&self) -> Vec<(String, usize)> {
+        let dir_sizes = self.dir_sizes();
+        let mut listing: Vec<(String, usize)> = self.dirs()
+            .map(|dir| (self.path(dir), dir_sizes[dir]))
+            .collect();
+        listing.sort_by(|(left_path, left_size), (right_path, right_size)| {
+            right_size.cmp(left_size).then_with(|| left_path.cmp(right_path))
+        });
+        listing
+    }
+
+    /// Recreate the filesystem under `root` (which is `/`), with each file
+    /// sized but sparse, so even the big ones cost next to no disk.
+    pub fn materialize(&self, root: &Path) -> io::Result<()> {
+        fs::create_dir_all(root)?;
+        for (id, _) in self.walk(Self::ROOT).skip(1) {
+            // Names are checked on the way in, but this is about to truncate
+            // files, so make sure nothing can escape `root`.
+            let relative = PathBuf::from(self.path(id).trim_start_matches('/'));
+            if !relative.components().all(|component| matches!(component, Component::Normal(_))) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{} would land outside {}", self.path(id), root.display()),
+                ))
+            }
+            let path = root.join(relative);
+            match self.nodes[id].kind {
+                FsNodeKind::Dir => fs::create_dir_all(&path)?,
+                FsNodeKind::File { size } => fs::File::create(&path)?.set_len(size as u64)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// `materialize` into a fresh directory under the system temp dir.
+    /// Returns where it went; cleaning up is up to the caller.
+    pub fn materialize_in_temp_dir(&self) -> io::Result<PathBuf> {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_nanos());
+        let root = std::env::temp_dir().join(format!("aoc2022-fs-{}-{nanos}", std::process::id()));
+        self.materialize(&root)?;
+        Ok(root)
+    }
+
+    /// Write the filesystem as a (ustar) tar archive. Files are full of zeros,
+    /// since the transcript never said what was in them.
+    pub fn write_tar(&self, mut writer: impl Write) -> io::Result<()> {
+        for (id, _) in self.walk(Self::ROOT).skip(1) {
+            let path = self.path(id).trim_start_matches('/').to_string();
+            match self.nodes[id].kind {
+                FsNodeKind::Dir => writer.write_all(&tar_header(&(path + "/"), 0, true)?)?,
+                FsNodeKind::File { size } => {
+                    writer.write_all(&tar_header(&path, size, false)?)?;
+                    // Contents, padded out to a whole block.
+                    io::copy(&mut io::repeat(0).take(size.div_ceil(512) as u64 * 512), &mut writer)?;
+                },
+            }
+        }
+        // Two empty blocks mark the end of the archive.
+        writer.write_all(&[0; 1024])?;
+        writer.flush()
+    }
+
+    /// Read a real directory tree into an `FsTree`, with `path` as `/`.
+    /// Entries come in name order; symlinks are skipped so loops can't happen.
+    pub fn from_directory(path: &Path) -> io::Result<Self> {
+        let mut tree = Self::new();
+        tree.read_directory(Self::ROOT, path)?;
+        Ok(tree)
+    }
+
+    fn read_directory(&mut self, dir: usize, path: &Path) -> io::Result<()> {
+        let mut entries: Vec<fs::DirEntry> = fs::read_dir(path)?.collect::<io::Result<_>>()?;
+        entries.sort_by_key(|entry| entry.file_name());
+        for entry in entries {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let metadata = fs::symlink_metadata(entry.path())?;
+            if metadata.is_dir() {
+                let child = self.add_dir(dir, &name);
+                self.read_directory(child, &entry.path())?;
+            } else if metadata.is_file() {
+                self.add_file(dir, &name, metadata.len() as usize);
+            }
+        }
+        Ok(())
+    }
+
+    /// A `$ cd`/`$ ls` transcript that rebuilds this filesystem: list a
+    /// directory, then visit each of its subdirectories in turn.
+    pub fn to_transcript(&self) -> String {
+        let mut transcript = String::from("$ cd /\n");
+        self.write_listing(Self::ROOT, &mut transcript);
+        transcript
+    }
+
+    fn write_listing(&self, dir: usize, transcript: &mut String) {
+        transcript.push_str("$ ls\n");
+        for &child in &self.nodes[dir].children {
+            let node = &self.nodes[child];
+            match node.kind {
+                FsNodeKind::Dir => transcript.push_str(&format!("dir {}\n", node.name)),
+                FsNodeKind::File { size } => transcript.push_str(&format!("{size} {}\n", node.name)),
+            }
+        }
+        for &child in self.nodes[dir].children.iter().filter(|&&child| self.nodes[child].is_dir()) {
+            transcript.push_str(&format!("$ cd {}\n", self.nodes[child].name));
+            self.write_listing(child, transcript);
+            transcript.push_str("$ cd ..\n");
+        }
+    }
+
+    /// The transcript you'd get exploring a real directory. See
+    /// `from_directory` and `to_transcript`.
+    pub fn transcript_from_directory(path: &Path) -> io::Result<String> {
+        Ok(Self::from_directory(path)?.to_transcript())
+    }
+
+    /// What could be deleted to get `needed_space` free on a disk of
+    /// `total_space`.
+    pub fn deletion_report(&self, total_space: usize, needed_space: usize) -> DeletionReport {
+        let used = self.dir_sizes()[Self::ROOT];
+        let free = total_space.saturating_sub(used);
+        let to_free = needed_space.saturating_sub(free);
+        let mut candidates: Vec<(String, usize)> = Vec::new();
+        if to_free > 0 {
+            candidates = self.du()
+                .into_iter()
+                .filter(|&(_, size)| size >= to_free)
+                .collect();
+            candidates.sort_by_key(|(_, size)| *size);
+        }
+        DeletionReport {
+            used,
+            free,
+            to_free,
+            candidates,
         }
     }
 }
 
-impl Default for RopeEnd {
+impl Default for FsTree {
     fn default() -> Self {
         Self::new()
     }
 }
 
-/// Parse bash interactions.
-///
-/// Do them one at a time (input and output), accepting the current directory
-/// and the command. Return the current directory and update the borrowed file
-/// tree.
+/// Something in a transcript that `FsTree::parse_transcript` skipped over.
+/// Line numbers start at 1.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TranscriptWarning {
+    /// Anything other than `cd <path>` or a bare `ls`.
+    UnknownCommand { line: usize, command: String },
+    /// Output that doesn't follow an `ls`.
+    UnexpectedOutput { line: usize, output: String },
+    /// `ls` output that isn't `dir <name>` or `<size> <name>`, or whose name
+    /// couldn't be a real entry (empty, `.`, `..` or containing a `/`).
+    MalformedOutput { line: usize, output: String },
+    /// A file listed again with a different size. The new size wins.
+    SizeChanged { line: usize, path: String, old: usize, new: usize },
+    /// Something listed (or cd'd into) as a directory that's already known
+    /// as a file, or the other way around. The first sighting wins.
+    KindConflict { line: usize, path: String },
+}
+
+impl fmt::Display for TranscriptWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::UnknownCommand { line, command } => {
+                write!(f, "line {line}: unknown command `{command}`")
+            },
+            Self::UnexpectedOutput { line, output } => {
+                write!(f, "line {line}: output `{output}` without an `ls`")
+            },
+            Self::MalformedOutput { line, output } => {
+                write!(f, "line {line}: can't read `ls` output `{output}`")
+            },
+            Self::SizeChanged { line, path, old, new } => {
+                write!(f, "line {line}: {path} changed size from {old} to {new}")
+            },
+            Self::KindConflict { line, path } => {
+                write!(f, "line {line}: {path} is both a file and a directory")
+            },
+        }
+    }
+}
+
+/// How much space is in use and which directories could go to free up enough.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DeletionReport {
+    pub used: usize,
+    pub free: usize,
+    /// How much more needs freeing (0 if there's already enough).
+    pub to_free: usize,
+    /// (path, size) of every directory big enough to free `to_free` on its
+    /// own, smallest first. Empty if nothing needs deleting.
+    pub candidates: Vec<(String, usize)>,
+}
+
+impl fmt::Display for DeletionReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "used: {}, free: {}, to free: {}", self.used, self.free, self.to_free)?;
+        if self.to_free == 0 {
+            return writeln!(f, "Nothing needs deleting.")
+        }
+        for (path, size) in &self.candidates {
+            writeln!(f, "{size:>12}  {path}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A ustar header block for one archive entry.
+fn tar_header(path: &str, size: usize, is_dir: bool) -> io::Result<[u8; 512]> {
+    let too_long = || io::Error::new(io::ErrorKind::InvalidInput, format!("{path} is too long for tar"));
+    // Names over 100 bytes get split at a `/` into a prefix and a name.
+    let (prefix, name) = if path.len() <= 100 {
+        ("", path)
+    } else {
+        // Search bytes: `/` is ASCII, so wherever it's found is a char boundary.
+        let split = path.as_bytes()[..path.len().min(156)]
+            .iter()
+            .rposition(|&byte| byte == b'/')
+            .ok_or_else(too_long)?;
+        (&path[..split], &path[split+1..])
+    };
+    if name.len() > 100 || prefix.len() > 155 {
+        return Err(too_long())
+    }
+    // The size field only has room for 11 octal digits, i.e. under 8 GiB.
+    if size >= 1 << 33 {
+        let message = format!("{path} is too big for tar ({size} bytes)");
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, message))
+    }
+
+    let mut header = [0u8; 512];
+    let mut put = |offset: usize, field: &[u8]| header[offset..offset + field.len()].copy_from_slice(field);
+    put(0, name.as_bytes());
+    put(100, if is_dir { b"0000755\0" } else { b"0000644\0" });
+    put(108, b"0000000\0");  // uid
+    put(116, b"0000000\0");  // gid
+    put(124, format!("{size:011o}\0").as_bytes());
+    put(136, b"00000000000\0");  // mtime
+    put(148, b"        ");  // checksum counts as spaces while summing
+    put(156, if is_dir { b"5" } else { b"0" });
+    put(257, b"ustar\0");
+    put(263, b"00");
+    put(345, prefix.as_bytes());
+    let checksum: u32 = header.iter().map(|&byte| byte as u32).sum();
+    header[148..156].copy_from_slice(format!("{checksum:06o}\0 ").as_bytes());
+    Ok(header)
+}
+
+/// Whether `name` can be a single directory entry, i.e. isn't empty, `.` or
+/// `..` and won't be split up as a path.
+fn is_entry_name(name: &str) -> bool {
+    !name.is_empty() && name != "." && name != ".." && !name.contains('/')
+}
+
+/// Depth-first iterator over an `FsTree`. See `FsTree::walk`.
+pub struct FsWalk<'a> {
+    tree: &'a FsTree,
+    stack: Vec<(usize, usize)>,
+}
+
+impl Iterator for FsWalk<'_> {
+    type Item = (usize, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (id, depth) = self.stack.pop()?;
+        // Push backwards so children come out in the order they were added.
+        for &child in self.tree.nodes[id].children.iter().rev() {
+            self.stack.push((child, depth + 1));
+        }
+        Some((id, depth))
+    }
+}
+
+/// Find the start of a packet.
 ///
-/// CWD
-/// Owners of each file
-/// Size of each file
-pub fn parse_comms_comms<'a>(
-    mut cwd: String,
-    cmd: &'a str,
-    file_owners: &mut HashMap<String, Vec<String>>,
-    file_sizes: &mut HashMap<String, usize>,
-) -> String {
-    if cmd.trim().starts_with("cd") {
-        let dir = cmd.trim().strip_prefix("cd ").expect("You are no good at parsing.");
-        if dir == "/" {
-            return "/".to_string()
+/// A marker is `marker_size` characters in a row that are all different. The
+/// answer is the 1-based position of its last character, which is also how
+/// many characters have to be read before it's spotted. So `"abcd"` has a
+/// size 4 marker at 4. A marker can't be empty, so `marker_size` 0 (or one
+/// longer than the datastream) gives `None`.
+pub fn find_marker(datastream: &str, marker_size: usize) -> Option<usize> {
+    marker_ends(datastream.as_bytes(), marker_size).next()
+}
+
+/// Every location a marker ends at, not just the first. Markers can overlap.
+pub fn find_all_markers(datastream: &str, marker_size: usize) -> Vec<usize> {
+    marker_ends(datastream.as_bytes(), marker_size).collect()
+}
+
+fn marker_ends(bytes: &[u8], marker_size: usize) -> impl Iterator<Item = usize> + '_ {
+    let mut detector = MarkerDetector::new(marker_size);
+    bytes.iter().filter_map(move |&byte| detector.push(byte))
+}
+
+/// Looks for markers one byte at a time. Keeps a count of each byte in the
+/// window and how many distinct ones there are, so each step is constant time.
+#[derive(Clone, Debug)]
+pub struct MarkerDetector {
+    marker_size: usize,
+    window: VecDeque<u8>,
+    counts: [usize; 256],
+    distinct: usize,
+    position: usize,  // How many bytes we've seen.
+}
+
+impl MarkerDetector {
+    pub fn new(marker_size: usize) -> Self {
+        Self {
+            marker_size,
+            window: VecDeque::with_capacity(marker_size + 1),
+            counts: [0; 256],
+            distinct: 0,
+            position: 0,
+        }
+    }
+
+    pub fn marker_size(&self) -> usize {
+        self.marker_size
+    }
+
+    /// How many bytes have been pushed so far.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Add the next byte. If it finishes a marker, returns its location (the
+    /// same as `find_marker` would give).
+    pub fn push(&mut self, byte: u8) -> Option<usize> {
+        self.position += 1;
+        if self.marker_size == 0 {
+            return None
+        }
+        self.window.push_back(byte);
+        self.counts[byte as usize] += 1;
+        if self.counts[byte as usize] == 1 {
+            self.distinct += 1;
         }
-        if dir == ".." {
-            // This is dumb, but it'll work.
-            cwd = cwd.trim_end_matches('/').to_string();
-            let offset = cwd.rfind('/').unwrap();
-            cwd.truncate(offset);  // Keep the trailing '/'
-            return cwd + "/"
+        if self.window.len() > self.marker_size {
+            let old = self.window.pop_front().unwrap() as usize;
+            self.counts[old] -= 1;
+            if self.counts[old] == 0 {
+                self.distinct -= 1;
+            }
         }
-        return cwd + dir + "/";  // One has to be owned - the other is borrowed.
+        (self.window.len() == self.marker_size && self.distinct == self.marker_size).then_some(self.position)
     }
+}
 
-    // Command must start with ls.
-    if !cmd.trim().starts_with("ls") {
-        println!("{:?}", cmd);
-        panic!("Unsupported command");
+/// The two markers a device cares about.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MarkerKind {
+    Packet,
+    Message,
+}
+
+impl MarkerKind {
+    pub const ALL: [Self; 2] = [Self::Packet, Self::Message];
+
+    /// How many distinct characters make one.
+    pub fn size(&self) -> usize {
+        match self {
+            Self::Packet => 4,
+            Self::Message => 14,
+        }
     }
-    let parts: Vec<&str> = cmd.split('\n').map(str::trim).collect();
-    for part in parts {
-        if part == "ls" {
-            continue
+}
+
+impl fmt::Display for MarkerKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Packet => write!(f, "start-of-packet"),
+            Self::Message => write!(f, "start-of-message"),
         }
-        // println!("{:?}", part);
-        let (type_or_size, name) = part.split_once(' ').unwrap();
-        if type_or_size == "dir" {
-            // I don't need to do anything with this.
-            continue
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Marker {
+    pub kind: MarkerKind,
+    pub position: usize,
+}
+
+impl fmt::Display for Marker {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} marker after character {}", self.kind, self.position)
+    }
+}
+
+/// Watches a datastream for the first packet and first message marker.
+#[derive(Clone, Debug)]
+pub struct MarkerScanner {
+    detectors: Vec<(MarkerKind, MarkerDetector)>,  // Only the ones still looking.
+}
+
+impl MarkerScanner {
+    pub fn new() -> Self {
+        Self {
+            detectors: MarkerKind::ALL.iter().map(|&kind| (kind, MarkerDetector::new(kind.size()))).collect(),
         }
-        let size = type_or_size.parse::<usize>().expect("Not a number!?");
-        let mut parents: Vec<String> = Vec::new();
-        // Be explicit about the location of a folder to protect against repeat
-        // names in different parent directories.
-        let mut parent: String = cwd.to_owned();
-        let filename = parent.to_owned() + name;
-        loop {
-            let offset = parent.rfind('/').unwrap();
-            parent.truncate(offset);
-            if parent.trim().is_empty() {
+    }
+
+    /// True once every kind of marker has been found.
+    pub fn is_done(&self) -> bool {
+        self.detectors.is_empty()
+    }
+
+    /// Add the next byte, getting back any markers it finished.
+    pub fn push(&mut self, byte: u8) -> Vec<Marker> {
+        let mut found = Vec::new();
+        self.detectors.retain_mut(|(kind, detector)| match detector.push(byte) {
+            Some(position) => {
+                found.push(Marker { kind: *kind, position });
+                false
+            },
+            None => true,
+        });
+        found
+    }
+
+    /// Add a chunk of bytes, stopping early once there's nothing left to find.
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<Marker> {
+        let mut found = Vec::new();
+        for &byte in bytes {
+            if self.is_done() {
                 break
             }
-            parents.push(parent.to_owned());
+            found.extend(self.push(byte));
         }
-        file_sizes.insert(filename.to_owned(), size);
-        file_owners.insert(filename.to_owned(), parents);
+        found
     }
 
-    cwd
+    /// Scan a byte iterator, handing back markers as they're found.
+    pub fn scan_bytes<I: IntoIterator<Item = u8>>(mut self, bytes: I) -> impl Iterator<Item = Marker> {
+        let mut bytes = bytes.into_iter();
+        let mut pending = VecDeque::new();
+        std::iter::from_fn(move || {
+            while pending.is_empty() && !self.is_done() {
+                pending.extend(self.push(bytes.next()?));
+            }
+            pending.pop_front()
+        })
+    }
 }
 
-/// Find the start of a packet.
-///
-/// The location of the last character in a block of 4 unique characters.
-///
-/// Plan is to use a VecDeque to scroll through the string, then use
-/// array_tools:Vec:Uniq to find when all four characters are different.
-pub fn find_marker(datastream: &str, marker_size: usize) -> usize {
-    let mut buffer: VecDeque<u8> = VecDeque::with_capacity(marker_size);
-    for (marker_location, character) in datastream.as_bytes().iter().enumerate() {
-        if buffer.len() < marker_size {
-            buffer.push_back(*character);
-            continue;
+impl Default for MarkerScanner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Markers from anything readable, read a chunk at a time so the whole
+/// datastream never has to be in memory.
+pub struct MarkerStream<R: Read> {
+    reader: R,
+    scanner: MarkerScanner,
+    chunk: Vec<u8>,
+    pending: VecDeque<Marker>,
+}
+
+impl<R: Read> MarkerStream<R> {
+    pub const CHUNK_SIZE: usize = 8192;
+
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            scanner: MarkerScanner::new(),
+            chunk: vec![0; Self::CHUNK_SIZE],
+            pending: VecDeque::new(),
         }
-        let unique_check = Vec::from_iter(buffer.iter());
-        if unique_check.is_unique() {
-            return marker_location
+    }
+}
+
+impl<R: Read> Iterator for MarkerStream<R> {
+    type Item = io::Result<Marker>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.pending.is_empty() {
+            if self.scanner.is_done() {
+                return None
+            }
+            let n_read = match self.reader.read(&mut self.chunk) {
+                Ok(0) => return None,
+                Ok(n_read) => n_read,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Some(Err(e)),
+            };
+            self.pending.extend(self.scanner.feed(&self.chunk[..n_read]));
         }
-        buffer.pop_front();
-        buffer.push_back(*character);
+        self.pending.pop_front().map(Ok)
     }
+}
 
-    panic!("Didn't find the packet marker!")
+/// Which crane is doing the lifting.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Crane {
+    /// Moves crates one at a time, so a moved pile ends up upside down.
+    #[default]
+    CrateMover9000,
+    /// Moves the whole pile at once, keeping its order.
+    CrateMover9001,
+}
+
+/// `move count from from to to`, from line `line` of the input.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CraneMove {
+    pub count: usize,
+    pub from: usize,
+    pub to: usize,
+    pub line: usize,
+}
+
+/// What went wrong reading or rearranging the crates. Lines and columns
+/// start at 1.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CrateYardError {
+    MissingLabels,
+    BadStackLabel { line: usize, label: String },
+    DuplicateStack { line: usize, stack: usize },
+    UnclosedCrate { line: usize, column: usize },
+    StrayCharacter { line: usize, column: usize, character: char },
+    StrayCrate { line: usize, column: usize },
+    CrateCollision { line: usize, stack: usize },
+    BadMove { line: usize },
+    UnknownStack { line: usize, stack: usize },
+    NotEnoughCrates { line: usize, stack: usize, wanted: usize, available: usize },
+}
+
+impl fmt::Display for CrateYardError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::MissingLabels => write!(f, "no stack labels under the drawing"),
+            Self::BadStackLabel { line, label } => write!(f, "line {line}: `{label}` isn't a stack number"),
+            Self::DuplicateStack { line, stack } => write!(f, "line {line}: stack {stack} is labelled twice"),
+            Self::UnclosedCrate { line, column } => write!(f, "line {line}, column {column}: crate is missing its `]`"),
+            Self::StrayCharacter { line, column, character } => {
+                write!(f, "line {line}, column {column}: unexpected `{character}`")
+            },
+            Self::StrayCrate { line, column } => {
+                write!(f, "line {line}, column {column}: crate isn't above any stack label")
+            },
+            Self::CrateCollision { line, stack } => write!(f, "line {line}: two crates on stack {stack}"),
+            Self::BadMove { line } => write!(f, "line {line}: expected `move N from A to B`"),
+            Self::UnknownStack { line, stack } => write!(f, "line {line}: there's no stack {stack}"),
+            Self::NotEnoughCrates { line, stack, wanted, available } => {
+                write!(f, "line {line}: can't move {wanted} crates off stack {stack}, it only has {available}")
+            },
+        }
+    }
+}
+
+impl std::error::Error for CrateYardError {}
+
+/// The stacks of crates, in the order their labels appear in the drawing.
+/// Each stack is bottom first, so the top crate is the end of the Vec.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CrateYard {
+    stacks: Vec<(usize, Vec<String>)>,
+}
+
+impl CrateYard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read the whole puzzle input: the drawing, a blank line, then moves.
+    pub fn parse(input: &str) -> Result<(Self, Vec<CraneMove>), CrateYardError> {
+        let lines: Vec<&str> = input.lines().collect();
+        let split = lines.iter().position(|line| line.trim().is_empty()).unwrap_or(lines.len());
+        let yard = Self::parse_drawing(&lines[..split].join("\n"))?;
+        let moves = lines.iter()
+            .enumerate()
+            .skip(split)
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(i, line)| parse_crane_move(line, i + 1))
+            .collect::<Result<_, _>>()?;
+        Ok((yard, moves))
+    }
+
+    /// Read just the drawing. The last line has the stack numbers, and each
+    /// `[crate]` goes on whichever stack's number sits under it. Crates can
+    /// be more than one character wide, and lines don't have to be padded.
+    pub fn parse_drawing(drawing: &str) -> Result<Self, CrateYardError> {
+        let lines: Vec<&str> = drawing.lines().collect();
+        let label_line = lines.iter().rposition(|line| !line.trim().is_empty()).ok_or(CrateYardError::MissingLabels)?;
+
+        // Column spans of each stack number.
+        let mut labels: Vec<(usize, [usize; 2])> = Vec::new();
+        for (label, span) in drawing_tokens(lines[label_line]) {
+            let line = label_line + 1;
+            let stack = label.parse().map_err(|_| CrateYardError::BadStackLabel { line, label: label.clone() })?;
+            if labels.iter().any(|&(id, _)| id == stack) {
+                return Err(CrateYardError::DuplicateStack { line, stack })
+            }
+            labels.push((stack, span));
+        }
+
+        let mut yard = CrateYard { stacks: labels.iter().map(|&(id, _)| (id, Vec::new())).collect() };
+        // Bottom row first, so crates stack up in order.
+        for (i, row) in lines[..label_line].iter().enumerate().rev() {
+            let line = i + 1;
+            let mut filled = vec![false; labels.len()];
+            for (token, [start, end]) in drawing_tokens(row) {
+                if !token.starts_with('[') {
+                    let character = token.chars().next().unwrap();
+                    return Err(CrateYardError::StrayCharacter { line, column: start + 1, character })
+                }
+                if !token.ends_with(']') || token.len() < 2 {
+                    return Err(CrateYardError::UnclosedCrate { line, column: start + 1 })
+                }
+                // Whichever label overlaps the crate the most.
+                let (index, overlap) = labels.iter()
+                    .map(|(_, [label_start, label_end])| end.min(*label_end).saturating_sub(start.max(*label_start)))
+                    .enumerate()
+                    .max_by_key(|&(index, overlap)| (overlap, Reverse(index)))
+                    .ok_or(CrateYardError::StrayCrate { line, column: start + 1 })?;
+                if overlap == 0 {
+                    return Err(CrateYardError::StrayCrate { line, column: start + 1 })
+                }
+                if filled[index] {
+                    return Err(CrateYardError::CrateCollision { line, stack: labels[index].0 })
+                }
+                filled[index] = true;
+                yard.stacks[index].1.push(token[1..token.len() - 1].to_string());
+            }
+        }
+        Ok(yard)
+    }
+
+    pub fn n_stacks(&self) -> usize {
+        self.stacks.len()
+    }
+
+    /// Stack numbers, in drawing order.
+    pub fn stack_ids(&self) -> impl Iterator<Item = usize> + '_ {
+        self.stacks.iter().map(|(id, _)| *id)
+    }
+
+    /// The crates on a stack, bottom first.
+    pub fn stack(&self, id: usize) -> Option<&[String]> {
+        self.stacks.iter().find(|(stack, _)| *stack == id).map(|(_, crates)| crates.as_slice())
+    }
+
+    fn stack_index(&self, id: usize, line: usize) -> Result<usize, CrateYardError> {
+        self.stacks.iter().position(|(stack, _)| *stack == id).ok_or(CrateYardError::UnknownStack { line, stack: id })
+    }
+
+    /// Do one move. Nothing changes if it fails.
+    pub fn apply(&mut self, crane_move: &CraneMove, crane: Crane) -> Result<(), CrateYardError> {
+        let CraneMove { count, from, to, line } = *crane_move;
+        let from_index = self.stack_index(from, line)?;
+        let to_index = self.stack_index(to, line)?;
+        let available = self.stacks[from_index].1.len();
+        if count > available {
+            return Err(CrateYardError::NotEnoughCrates { line, stack: from, wanted: count, available })
+        }
+        // Lifting crates off a stack and putting them straight back changes
+        // nothing, whichever crane does it.
+        if from_index == to_index {
+            return Ok(())
+        }
+        let mut pile = self.stacks[from_index].1.split_off(available - count);
+        if crane == Crane::CrateMover9000 {
+            pile.reverse();
+        }
+        self.stacks[to_index].1.extend(pile);
+        Ok(())
+    }
+
+    /// Do all the moves, stopping at the first one that fails.
+    pub fn apply_moves(&mut self, moves: &[CraneMove], crane: Crane) -> Result<(), CrateYardError> {
+        moves.iter().try_for_each(|crane_move| self.apply(crane_move, crane))
+    }
+
+    /// The top crate of each stack, run together. Empty stacks add nothing.
+    pub fn tops(&self) -> String {
+        self.stacks.iter().filter_map(|(_, crates)| crates.last()).map(String::as_str).collect()
+    }
+}
+
+impl fmt::Display for CrateYard {
+    /// Draw it back out the way the puzzle does.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let width = self.stacks.iter()
+            .flat_map(|(id, crates)| crates.iter().map(|label| label.len() + 2).chain([id.to_string().len()]))
+            .max()
+            .unwrap_or(0);
+        let height = self.stacks.iter().map(|(_, crates)| crates.len()).max().unwrap_or(0);
+        for level in (0..height).rev() {
+            let row = self.stacks.iter()
+                .map(|(_, crates)| match crates.get(level) {
+                    Some(label) => format!("{:^width$}", format!("[{label}]")),
+                    None => " ".repeat(width),
+                })
+                .join(" ");
+            writeln!(f, "{}", row.trim_end())?;
+        }
+        write!(f, "{}", self.stacks.iter().map(|(id, _)| format!("{id:^width$}")).join(" ").trim_end())
+    }
+}
+
+/// Whitespace-separated words of a drawing line, with their (0-based,
+/// end-exclusive) column spans. Crates are kept whole, even with spaces in.
+fn drawing_tokens(line: &str) -> Vec<(String, [usize; 2])> {
+    let characters: Vec<char> = line.chars().collect();
+    let mut tokens = Vec::new();
+    let mut column = 0;
+    while column < characters.len() {
+        if characters[column].is_whitespace() {
+            column += 1;
+            continue
+        }
+        let start = column;
+        if characters[column] == '[' {
+            while column < characters.len() && characters[column] != ']' {
+                column += 1;
+            }
+            column = (column + 1).min(characters.len());
+        } else {
+            while column < characters.len() && !characters[column].is_whitespace() {
+                column += 1;
+            }
+        }
+        tokens.push((characters[start..column].iter().collect(), [start, column]));
+    }
+    tokens
 }
 
-/// Parse crates arrangement.
-///
-/// This'll rearrange the visual stacks into vectors that I can push and pop
-/// from. I need LIFO (stack) effect. Vec offers this with pop_back()
-/// (https://stackoverflow.com/a/40851723).
-///
-/// Each column is a stack, which kinda blows, not gonna lie. What I'll do is
-/// split in chunks of 4 characters. The last one will be 3 characters long
-/// since the newline will have been dumped. I can then .extend() each stack
-/// with the returned empty or single-value vec. Other option is to use regex on
-/// each row. I actually like that better... time to learn regex for rust.
-pub fn create_stack_regex(crate_labels: &str) -> Vec<Regex> {
-    println!("{:?}", crate_labels);
-    let mut finders: Vec<Regex> = Vec::new();
-    for i in (0..crate_labels.len()).step_by(4) {
-        let expression = format!(r"[\n^]+.{{{}}}\[(\w)\]", i);
-        finders.push(Regex::new(expression.as_str()).expect("Write better expressions, my dude."));
-    }
-    println!("{:?}", finders);
-    finders
-}
-
-/// Rearrange crates as commanded.
-///
 /// Command format is move a from b to c
-pub fn make_moves(moves: &str, stacks: &mut [Vec<&str>]) {
-    let expression = Regex::new(r"move (\d+) from (\d+) to (\d+)").unwrap();
-    let captures = expression.captures(moves).expect("Couldn't recognize the command.");
-    let n = captures.get(1).unwrap().as_str().parse::<usize>().unwrap();
-    let from = captures.get(2).unwrap().as_str().parse::<usize>().unwrap() - 1;
-    let to = captures.get(3).unwrap().as_str().parse::<usize>().unwrap() - 1;
-    let mut moving_stack: Vec<&str> = Vec::new();
-    for _ in 0..n {
-        moving_stack.push(stacks[from].pop().expect("The `from` stack is emptry!"));
-    }
-    // Comment out for part 1
-    moving_stack.reverse();
-    stacks[to].extend(moving_stack);
+fn parse_crane_move(text: &str, line: usize) -> Result<CraneMove, CrateYardError> {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    match words.as_slice() {
+        ["move", count, "from", from, "to", to] => {
+            let number = |word: &str| word.parse::<usize>().map_err(|_| CrateYardError::BadMove { line });
+            Ok(CraneMove { count: number(count)?, from: number(from)?, to: number(to)?, line })
+        },
+        _ => Err(CrateYardError::BadMove { line }),
+    }
 }
 
 /// Find ranges that fully contain other ranges.
@@ -1,21 +1,29 @@
 #![allow(dead_code)]
-use std::{collections::HashMap, cmp::Ordering};
-use array2d::Array2D;
-use itertools::Itertools;
+use std::{
+    cmp::Ordering,
+    fs,
+};
 
 use aoc2022::{
     read_file,
+    Forest,
     Cave,
     compare_lists,
-    get_next_node_coords,
-    MapNode,
+    HeightMap,
+    ClimbRule,
     CRT,
     CPU,
-    RopeEnd,
-    parse_comms_comms,
-    find_marker,
-    create_stack_regex,
-    make_moves,
+    Device,
+    SignalSampler,
+    PeripheralOutput,
+    parse_program,
+    Rope,
+    parse_rope_moves,
+    FsTree,
+    MarkerStream,
+    MarkerKind,
+    CrateYard,
+    Crane,
     is_full_overlap,
     is_partial_overlap,
     find_missort,
@@ -96,342 +104,122 @@ fn day13() {
 /// Path planning
 fn day12() {
     let height_map = read_file("data/day12.txt");
-    let mut map: HashMap<[isize; 2], MapNode> = HashMap::new();
-
-    let mut _destination_node: [isize; 2] = [0, 0];
-
-    for (y, row) in height_map.split('\n').enumerate() {
-        for (x, character) in row.chars().enumerate() {
-            let coords = [x as isize, y as isize];
-            let elevation: isize;
-            if character == 'S' {
-                elevation = 'a' as isize;
-            } else if character == 'E' {
-                _destination_node = coords;
-                elevation = 'z' as isize;
-            } else {
-                elevation = character as isize;
-            };
-            // map.insert(coords, MapNode::new(x as isize, y as isize, elevation, character=='S'));
-            map.insert(coords, MapNode::new(x as isize, y as isize, elevation, character=='E'));
-        }
-    }
-
-    'djikstra: loop {
-        let next_node_coords = get_next_node_coords(&map);
-
-        match next_node_coords {
-            Some(node_coords) => {
-                // if node_coords == _destination_node {  // Optimization for when the destination is known
-                //     break 'djikstra
-                // }
-                {
-                    let current_node = map.get(&node_coords).expect("No node here...").clone();
-                    if current_node.distance_from_initial == usize::MAX {
-                        // We've hit the end of visitable or reasonable nodes.
-                        break 'djikstra
-                    }
-                    // for neighbor in map.values_mut().filter(|node| current_node.is_upward_neighbor(node) && !node.is_visited) {
-                    for neighbor in map.values_mut().filter(|node| current_node.is_downward_neighbor(node) && !node.is_visited) {
-                        // println!("({}, {})", neighbor.x, neighbor.y);
-                        let current_distance = current_node.distance_from_initial + 1;
-                        if current_distance < neighbor.distance_from_initial {
-                            neighbor.distance_from_initial = current_distance;
-                        }
-                    }
-                }
-                let current_node = map.get_mut(&node_coords).unwrap();
-                current_node.is_visited = true;
-            },
-            None => break,
-        }
-    }
-    // println!("Day 12, Part 1: {}", map.get(&_destination_node).unwrap().distance_from_initial);
-    let part2 = map.values().filter(|node| node.elevation == 'a' as isize).min().unwrap();
-    println!("Day 12, Part 2: ({}, {}): {}", part2.x, part2.y, part2.distance_from_initial);
+    let map = HeightMap::parse(height_map.trim());
+    let rule = ClimbRule::default();
+
+    let part1 = map
+        .shortest_distance(&[map.start], map.end, &rule)
+        .expect("Can't get to the summit from the start.");
+    println!("Day 12, Part 1: {part1}");
+
+    // Every `a` is a candidate trailhead, so search from all of them at once.
+    let trailheads = map.cells_at_elevation('a');
+    let part2 = map
+        .shortest_distance(&trailheads, map.end, &rule)
+        .expect("Can't get to the summit from any trailhead.");
+    println!("Day 12, Part 2: {part2}");
 }
 
 /// Rebuild the video/cpu for the comms device.
 fn day10() {
     let operations = read_file("data/day10.txt");
+    let program = parse_program(&operations).unwrap_or_else(|e| panic!("{e}"));
     let mut cpu = CPU::new();
-    let mut crt = CRT::new();
-    // Queue up all operations.
-    for operation in operations.split('\n').map(|x| x.trim()) {
-        if operation.trim().is_empty() {
-            continue
-        }
-        if operation == "noop" {
-            cpu.noop();
-        } else if operation.starts_with("addx") {
-            let dx = operation.split(' ').last().unwrap().parse::<isize>().unwrap();
-            cpu.addx(dx);
+    cpu.load_program(&program);
+
+    let mut device = Device::new(cpu, CRT::default());
+    device.plug_in(Box::new(SignalSampler::default()));
+    for output in device.run() {
+        match output {
+            PeripheralOutput::SignalStrength(signal_strength) => {
+                println!("Day 10, Part 1: {signal_strength}")
+            },
+            PeripheralOutput::Screen(screen) => print!("{screen}"),
+            PeripheralOutput::Trace(_) => {},
         }
     }
-
-    let cycles_of_mild_interest: Vec<usize> = vec![20, 60, 100, 140, 180, 220];
-    let mut signal_strength: isize = 0;
-    let mut current_cycle: usize;
-    'frankie: loop {
-        match cpu.execute_clock_cycle() {
-            Ok(cycle) => current_cycle = cycle,
-            Err(_) => break 'frankie,
-        }
-        crt.lazer_beam_it(current_cycle, cpu.x);
-
-        if cycles_of_mild_interest.contains(&current_cycle) {
-            signal_strength += cpu.get_signal_strength()
-        }
+    match device.crt.read_letters(0) {
+        Ok(letters) => println!("Day 10, Part 2: {letters}"),
+        Err(e) => println!("Day 10, Part 2: {e}"),
     }
-
-    println!("Day 10, Part 1: {signal_strength}");
-    crt.visualize();
 }
 
 /// Calculate rope motion.
 fn day9() {
-    let moves = read_file("data/day9.txt");
-    let part = 2;
-    let rope_length: usize =
-        if part == 1 {
-            2
-        } else {
-            10
-        };
-    let mut rope: Vec<RopeEnd> = Vec::new();
-    for _ in 0..rope_length {
-        rope.push(RopeEnd::new());
-    }
-    let directions_map: HashMap<&str, [isize; 2]> = HashMap::from([
-        ("U", [0, 1]),
-        ("D", [0, -1]),
-        ("L", [-1, 0]),
-        ("R", [1, 0]),
-    ]);
-
-    for move_ in moves.split('\n') {
-        if move_.trim().is_empty() {
-            continue
-        }
-        let (direction, num_steps) = move_
-            .split_once(' ')
-            .expect("Move isn't formatted as expected");
-        let move_coords = directions_map
-            .get(direction)
-            .expect("Unsupported move direction");
-        for _ in 0..num_steps.parse().unwrap() {
-            for planck_length_idx in 0..(rope.len()-1) {
-                if planck_length_idx == 0 {
-                    rope[planck_length_idx].move_delta(*move_coords);
-                }
-                // Get two mutable references by snagging a slice. Not totally
-                // clear on how this
-                let (head, tail) = if let [head, tail] = &mut rope[planck_length_idx..=planck_length_idx+1] {
-                    Some((head, tail))
-                } else {
-                    None
-                }.unwrap();
-                tail.follow(head);
-            }
-            // println!("({}, {}), ({}, {})", head.x, head.y, tail.x, tail.y);
-        }
-    }
-
-    let total_visits = rope[rope_length-1].visited_spaces.len();
-    println!("Day 9, Part {part}: {total_visits}")
+    let moves = parse_rope_moves(&read_file("data/day9.txt")).unwrap_or_else(|e| panic!("{e}"));
+    let mut rope = Rope::new(10);
+    rope.apply_moves(&moves);
+
+    // The knot right behind the head moves exactly like the tail of a 2-knot
+    // rope, so one long rope answers both parts.
+    println!("Day 9, Part 1: {}", rope.n_visited(1));
+    println!("Day 9, Part 2: {}", rope.n_visited(9));
 }
 
 /// Plan a treehouse.
 fn day8() {
-    let contents = read_file("data/day8.txt");
-    let mut grid_rows: Vec<Vec<i8>> = Vec::new();
-    for row in contents.split('\n').map(str::trim) {
-        if row.is_empty() {
-            continue
-        }
-        let mut grid_row: Vec<i8> = Vec::with_capacity(row.len());
-        for digit in row.chars() {
-            let digit_str = digit.to_string();
-            grid_row.push(digit_str.parse::<i8>().expect("Not a number???"));
-        }
-        grid_rows.push(grid_row);
-    }
-    let grid: Array2D<i8> = Array2D::from_rows(&grid_rows);
-
-    let mut is_visible: Array2D<bool> = Array2D::filled_with(false, grid.num_rows(), grid.num_columns());
-    let mut scenic_score: Array2D<usize> = Array2D::filled_with(0, grid.num_rows(), grid.num_columns());
-    // Trees can only be hidden if not on the edge. Similarly, scenic score can
-    // only be non-zero is not on the edge.
-    for (row_idx, col_idx) in (1..grid.num_rows()-1).cartesian_product(1..grid.num_columns()-1) {
-        let tree_height = grid[(row_idx, col_idx)];
-        scenic_score[(row_idx, col_idx)] = 1;
-        // println!("{row_idx}, {col_idx}, {tree_height}");
-
-        let mut row = grid.row_iter(row_idx);
-        // Take the part of the row after col_idx
-        let taller = row.enumerate()
-            .filter(|&(idx, h)| idx>col_idx && *h>=tree_height)
-            .map(|(idx, _)| idx)
-            .next();
-        match taller {
-            Some(idx) => {
-                // println!("{col_idx} --> {idx}");
-                scenic_score[(row_idx, col_idx)] *= idx - col_idx;
-            },
-            None => {
-                let end = grid.num_columns() - 1 - col_idx;
-                // println!("{col_idx} --> {end}");
-                is_visible[(row_idx, col_idx)] = true;
-                scenic_score[(row_idx, col_idx)] *= end;
-            },
-        }
-        row = grid.row_iter(row_idx);
-        let taller = row.enumerate()
-            .filter(|&(idx, h)| idx<col_idx && *h>=tree_height)
-            .map(|(idx, _)| idx)
-            .last();
-        match taller {
-            Some(idx) => {
-                scenic_score[(row_idx, col_idx)] *= col_idx - idx;
-            },
-            None => {
-                is_visible[(row_idx, col_idx)] = true;
-                scenic_score[(row_idx, col_idx)] *= col_idx;
-            },
-        }
+    let forest = Forest::parse(&read_file("data/day8.txt")).unwrap_or_else(|e| panic!("{e}"));
+    print!("{}", forest.visibility().render(|_, &visible| if visible { '#' } else { '.' }));
+    println!("Day 8, Part 1: {}", forest.n_visible());
 
-        let mut col = grid.column_iter(col_idx);
-        // Take the part of the row after row
-        let taller = col.enumerate()
-            .filter(|&(idx, h)| idx>row_idx && *h>=tree_height)
-            .map(|(idx, _)| idx)
-            .next();
-        match taller {
-            Some(idx) => {
-                scenic_score[(row_idx, col_idx)] *= idx - row_idx;
-            },
-            None => {
-                is_visible[(row_idx, col_idx)] = true;
-                scenic_score[(row_idx, col_idx)] *= grid.num_rows() - 1 - row_idx;
-            },
-        }
-        col = grid.column_iter(col_idx);
-        let taller = col.enumerate()
-            .filter(|&(idx, h)| idx<row_idx && *h>=tree_height)
-            .map(|(idx, _)| idx)
-            .last();
-        match taller {
-            Some(idx) => {
-                scenic_score[(row_idx, col_idx)] *= row_idx - idx;
-            },
-            None => {
-                is_visible[(row_idx, col_idx)] = true;
-                scenic_score[(row_idx, col_idx)] *= row_idx;
-            },
-        }
-    }
-
-    let n_visible = is_visible.elements_row_major_iter().filter(|&b| *b).count()
-        + grid.num_columns()*2 + grid.num_rows()*2 - 4;  // Double counted the corners
-    println!("{is_visible:?}");
-    println!("Day 8, Part 1: {n_visible}");
-
-    let best_score = scenic_score.elements_row_major_iter().max().unwrap();
+    let best_score = forest.best_scenic_score().expect("No trees to score.");
     println!("Day 8, Part 2: {best_score}");
-
 }
 
 /// Find big files.
 fn day7() {
     let contents = read_file("data/day7.txt");
-    let mut cwd: String = "".to_owned();
-    let mut file_owners: HashMap<String, Vec<String>> = HashMap::new();
-    let mut file_sizes: HashMap<String, usize> = HashMap::new();
-    // Parse the commands and build out the tree.
-    for cmd in contents.split('$').map(str::trim) {
-        if cmd.is_empty() {
-            continue
-        }
-        cwd = parse_comms_comms(cwd, cmd, &mut file_owners, &mut file_sizes);
+    let (fs, warnings) = FsTree::parse_transcript(&contents);
+    for warning in warnings {
+        println!("Warning: {warning}");
     }
+    print!("{}", fs.render_tree());
 
-    println!("{:?}", file_owners);
-    println!("{:?}", file_sizes);
-
-    // Aggregate size of each directory.
-    let mut folder_sizes: HashMap<String, usize> = HashMap::new();
-    for file_name in file_owners.keys() {
-        let file_size = file_sizes.get(file_name).unwrap();
-        *folder_sizes.entry("/".to_string()).or_insert(0) += *file_size;
-        for folder in file_owners.get(file_name).unwrap() {
-            *folder_sizes.entry(folder.to_owned()).or_insert(0) += *file_size;
-        }
-    }
     // find those under the cap size.
-    let mut arbitrary_sum: usize = 0;
-    for folder in folder_sizes.values() {
-        if *folder <= 100000 {
-            arbitrary_sum += *folder
-        }
-    }
-    println!("{:?}", folder_sizes);
+    let arbitrary_sum: usize = fs.du()
+        .into_iter()
+        .map(|(_, size)| size)
+        .filter(|&size| size <= 100000)
+        .sum();
     println!("Day 7, Part 1: {}", arbitrary_sum);
 
-    let total_space = 70000000;
-    let needed_space = 30000000;
     // Find the smallest directory that, if deleted, would free up enough space
     // the file system.
-    let minimum_delete_size = folder_sizes.get("/").unwrap() - (total_space - needed_space);
-    let mut _smallest_possible_directory = "/";
-    let mut planned_delete_size = *folder_sizes.get("/").unwrap();
-    for (folder, size) in folder_sizes.iter() {
-        if *size > minimum_delete_size && *size < planned_delete_size {
-            planned_delete_size = *size;
-            _smallest_possible_directory = folder;
+    let report = fs.deletion_report(70000000, 30000000);
+    print!("{report}");
+    if report.to_free == 0 {
+        println!("Day 7, Part 2: 0 (already enough space, nothing to delete)");
+    } else {
+        match report.candidates.first() {
+            Some((_, planned_delete_size)) => println!("Day 7, Part 2: {}", planned_delete_size),
+            None => println!("Day 7, Part 2: nothing big enough to delete"),
         }
     }
-    println!("Day 7, Part 2: {}", planned_delete_size)
 }
 
 
 /// Packet detection
 fn day6() {
-    let contents = read_file("data/day6.txt");
-    let packet_flag: usize = find_marker(contents.as_str(), 4);
-    println!("Day 6, Part 1: {}", packet_flag);
-    let message_flag: usize = find_marker(contents.as_str(), 14);
-    println!("Day 6, Part 2: {}", message_flag);
+    let file = fs::File::open("data/day6.txt").expect("Couldn't open data/day6.txt");
+    for marker in MarkerStream::new(file) {
+        let marker = marker.expect("Couldn't read data/day6.txt");
+        let part = match marker.kind {
+            MarkerKind::Packet => 1,
+            MarkerKind::Message => 2,
+        };
+        println!("Day 6, Part {}: {}", part, marker.position);
+    }
 }
 
 /// FIgure out where the crates are going to be.
 fn day5() {
-    let mut crates = read_file("data/day5_order.txt");
-    crates = crates.trim_end().to_string();
-    let mut moves = read_file("data/day5_moves.txt");
-    moves = moves.trim_end().to_string();
-
-    let finders = create_stack_regex(crates.split('\n').last().expect("Shit"));
-
-    let mut stacks: Vec<Vec<&str>> = Vec::new();
-    for finder in finders {
-        let mut stack: Vec<&str> = Vec::new();
-
-        for stack_captures in finder.captures_iter(crates.as_str()) {
-            stack.push(stack_captures.get(1).unwrap().as_str());
-        }
-        // Top of the stack needs to be the end of the Vec.
-        stack.reverse();
-        stacks.push(stack);
-    }
-    for move_set in moves.split('\n') {
-        make_moves(move_set, &mut stacks)
+    let contents = read_file("data/day5.txt");
+    let (yard, moves) = CrateYard::parse(&contents).unwrap_or_else(|e| panic!("{e}"));
+    for (part, crane) in [(1, Crane::CrateMover9000), (2, Crane::CrateMover9001)] {
+        let mut yard = yard.clone();
+        yard.apply_moves(&moves, crane).unwrap_or_else(|e| panic!("{e}"));
+        println!("Day 5, Part {}: {}", part, yard.tops());
     }
-    print!("Day 5, Part 1: ");
-    for stack in stacks {
-        print!("{}", stack[stack.len()-1]);
-    }
-
-
 }
 
 /// Check for overlapped assignments between paired elves.
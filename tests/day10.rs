@@ -0,0 +1,651 @@
+use std::cmp::Ordering;
+
+use aoc2022::{
+    parse_program,
+    Breakpoint,
+    CpuFault,
+    Device,
+    Instruction,
+    NoSuchFrame,
+    OcrError,
+    PeripheralOutput,
+    ProgramError,
+    Register,
+    RunState,
+    StopReason,
+    TraceRecorder,
+    SignalSampler,
+    CPU,
+    CRT,
+};
+
+const EXAMPLE: &str = "\
+addx 15\naddx -11\naddx 6\naddx -3\naddx 5\naddx -1\naddx -8\naddx 13\naddx 4\nnoop\n\
+addx -1\naddx 5\naddx -1\naddx 5\naddx -1\naddx 5\naddx -1\naddx 5\naddx -1\naddx -35\n\
+addx 1\naddx 24\naddx -19\naddx 1\naddx 16\naddx -11\nnoop\nnoop\naddx 21\naddx -15\n\
+noop\nnoop\naddx -3\naddx 9\naddx 1\naddx -3\naddx 8\naddx 1\naddx 5\nnoop\n\
+noop\nnoop\nnoop\nnoop\naddx -36\nnoop\naddx 1\naddx 7\nnoop\nnoop\n\
+noop\naddx 2\naddx 6\nnoop\nnoop\nnoop\nnoop\nnoop\naddx 1\nnoop\n\
+noop\naddx 7\naddx 1\nnoop\naddx -13\naddx 13\naddx 7\nnoop\naddx 1\naddx -33\n\
+noop\nnoop\nnoop\naddx 2\nnoop\nnoop\nnoop\naddx 8\nnoop\naddx -1\n\
+addx 2\naddx 1\nnoop\naddx 17\naddx -9\naddx 1\naddx 1\naddx -3\naddx 11\nnoop\n\
+noop\naddx 1\nnoop\naddx 1\nnoop\nnoop\naddx -13\naddx -19\naddx 1\naddx 3\n\
+addx 26\naddx -30\naddx 12\naddx -1\naddx 3\naddx 1\nnoop\nnoop\nnoop\naddx -9\n\
+addx 18\naddx 1\naddx 2\nnoop\nnoop\naddx 9\nnoop\nnoop\nnoop\naddx -1\n\
+addx 2\naddx -37\naddx 1\naddx 3\nnoop\naddx 15\naddx -21\naddx 22\naddx -6\naddx 1\n\
+noop\naddx 2\naddx 1\nnoop\naddx -10\nnoop\nnoop\naddx 20\naddx 1\naddx 2\n\
+addx 2\naddx -6\naddx -11\nnoop\nnoop\nnoop\n";
+
+const EXAMPLE_SCREEN: &str = "\
+##..##..##..##..##..##..##..##..##..##..
+###...###...###...###...###...###...###.
+####....####....####....####....####....
+#####.....#####.....#####.....#####.....
+######......######......######......####
+#######.......#######.......#######.....
+";
+
+fn example_cpu() -> CPU {
+    let mut cpu = CPU::new();
+    cpu.load_program(&parse_program(EXAMPLE).unwrap());
+    cpu
+}
+
+fn example_device() -> Device {
+    let mut device = Device::new(example_cpu(), CRT::default());
+    device.plug_in(Box::new(SignalSampler::default()));
+    device
+}
+
+/// A CRT showing `rows` (`#` lit), drawn a pixel per cycle.
+fn crt_showing(rows: &[&str]) -> CRT {
+    let width = rows[0].len();
+    let mut crt = CRT::new(width, rows.len(), vec![0]);
+    for (i, pixel) in rows.concat().chars().enumerate() {
+        // Park the sprite where the beam is for lit pixels, off screen otherwise.
+        let sprite_pos = if pixel == '#' { (i % width) as isize } else { -10 };
+        crt.lazer_beam_it(i + 1, sprite_pos);
+    }
+    crt
+}
+
+#[test]
+fn example_signal_strength() {
+    let outputs = example_device().run();
+    assert!(outputs.contains(&PeripheralOutput::SignalStrength(13140)));
+}
+
+#[test]
+fn example_screen() {
+    let mut device = example_device();
+    let outputs = device.run();
+    assert_eq!(outputs[0], PeripheralOutput::Screen(EXAMPLE_SCREEN.to_string()));
+    assert_eq!(device.crt.n_frames(), 1);
+    assert_eq!(device.crt.render(), EXAMPLE_SCREEN);
+}
+
+#[test]
+fn example_cycle_count() {
+    let mut device = example_device();
+    device.run();
+    assert_eq!(device.cpu.cycle, 240);
+}
+
+#[test]
+fn read_known_glyphs() {
+    let crt = crt_showing(&[
+        "#..#..###.",
+        "#..#...#..",
+        "####...#..",
+        "#..#...#..",
+        "#..#...#..",
+        "#..#..###.",
+    ]);
+    assert_eq!(crt.read_letters(0), Ok("HI".to_string()));
+}
+
+#[test]
+fn blank_glyphs_read_as_spaces() {
+    let crt = crt_showing(&[
+        "......###.",
+        ".......#..",
+        ".......#..",
+        ".......#..",
+        ".......#..",
+        "......###.",
+    ]);
+    assert_eq!(crt.read_letters(0), Ok(" I".to_string()));
+}
+
+#[test]
+fn unrecognized_glyphs() {
+    let crt = crt_showing(&[
+        "####..###.",
+        "####...#..",
+        "####...#..",
+        "####...#..",
+        "####...#..",
+        "####..###.",
+    ]);
+    assert_eq!(
+        crt.read_letters(0),
+        Err(OcrError::Unrecognized { partial: "?I".to_string(), unrecognized: vec![0] }),
+    );
+}
+
+#[test]
+fn missing_frame() {
+    // A fresh screen has one blank frame.
+    assert_eq!(CRT::default().read_letters(0), Ok(" ".repeat(8)));
+    assert_eq!(CRT::default().read_letters(1), Err(OcrError::NoSuchFrame { frame: 1, n_frames: 1 }));
+    let crt = crt_showing(&["#"]);
+    assert_eq!(crt.read_letters(3), Err(OcrError::NoSuchFrame { frame: 3, n_frames: 1 }));
+}
+
+#[test]
+fn endless_loops_hit_the_cycle_limit() {
+    for program in ["jmp 0", "addx 1\njnz x -1"] {
+        let mut cpu = CPU::new();
+        cpu.load_program(&parse_program(program).unwrap());
+        cpu.set_cycle_limit(1000);
+        let mut device = Device::new(cpu, CRT::default());
+        device.run();
+        assert_eq!(device.cpu.state(), RunState::Faulted(CpuFault::CycleLimit { limit: 1000 }));
+        assert_eq!(device.cpu.cycle, 1000);
+    }
+}
+
+#[test]
+fn resume_stops_at_the_cycle_limit() {
+    let mut cpu = CPU::new();
+    cpu.load_program(&parse_program("jmp 0").unwrap());
+    cpu.set_cycle_limit(10);
+    assert_eq!(cpu.resume(), StopReason::Stopped(RunState::Faulted(CpuFault::CycleLimit { limit: 10 })));
+}
+
+#[test]
+fn programs_can_finish_right_at_the_limit() {
+    let mut device = example_device();
+    device.cpu.set_cycle_limit(240);
+    assert!(device.run().contains(&PeripheralOutput::SignalStrength(13140)));
+    assert_eq!(device.cpu.state(), RunState::Halted);
+}
+
+#[test]
+fn parse_every_instruction() {
+    let program = parse_program("noop\naddx -3\naddy 4\nmulx 2\njmp -1\njz x 2\njnz y -2\n").unwrap();
+    assert_eq!(
+        program,
+        vec![
+            Instruction::Noop,
+            Instruction::Addx(-3),
+            Instruction::Addy(4),
+            Instruction::Mulx(2),
+            Instruction::Jmp(-1),
+            Instruction::Jz(Register::X, 2),
+            Instruction::Jnz(Register::Y, -2),
+        ],
+    );
+    // Display gives back the source.
+    let source: Vec<String> = program.iter().map(Instruction::to_string).collect();
+    assert_eq!(source.join("\n"), "noop\naddx -3\naddy 4\nmulx 2\njmp -1\njz x 2\njnz y -2");
+}
+
+#[test]
+fn unknown_opcode() {
+    assert_eq!(
+        parse_program("noop\nsubx 3"),
+        Err(ProgramError::UnknownOpcode { line: 2, opcode: "subx".to_string() }),
+    );
+}
+
+#[test]
+fn missing_operand() {
+    assert_eq!(parse_program("addx"), Err(ProgramError::MissingOperand { line: 1, opcode: "addx".to_string() }));
+    assert_eq!(parse_program("jz x"), Err(ProgramError::MissingOperand { line: 1, opcode: "jz".to_string() }));
+}
+
+#[test]
+fn bad_operand() {
+    assert_eq!(parse_program("addx three"), Err(ProgramError::BadOperand { line: 1, operand: "three".to_string() }));
+    assert_eq!(parse_program("jnz z 1"), Err(ProgramError::BadOperand { line: 1, operand: "z".to_string() }));
+}
+
+#[test]
+fn extra_operands() {
+    assert_eq!(parse_program("noop 1"), Err(ProgramError::BadOperand { line: 1, operand: "1".to_string() }));
+    assert_eq!(parse_program("addx 1 2"), Err(ProgramError::BadOperand { line: 1, operand: "2".to_string() }));
+    assert_eq!(parse_program("jz x 1 y"), Err(ProgramError::BadOperand { line: 1, operand: "y".to_string() }));
+}
+
+#[test]
+fn line_numbers_count_blank_lines() {
+    assert_eq!(
+        parse_program("noop\n\n   \naddx 1\n\nbogus\n"),
+        Err(ProgramError::UnknownOpcode { line: 6, opcode: "bogus".to_string() }),
+    );
+    assert_eq!(parse_program("\n\nnoop\n\n").unwrap(), vec![Instruction::Noop]);
+}
+
+fn loaded(source: &str) -> CPU {
+    let mut cpu = CPU::new();
+    cpu.load_program(&parse_program(source).unwrap());
+    cpu
+}
+
+fn run(source: &str) -> CPU {
+    let mut cpu = loaded(source);
+    while cpu.execute_clock_cycle() == RunState::Running {}
+    cpu
+}
+
+#[test]
+fn instruction_effects() {
+    let mut registers = [1, 0];
+    assert_eq!(Instruction::Addx(4).execute(&mut registers), Ok(1));
+    assert_eq!(Instruction::Mulx(3).execute(&mut registers), Ok(1));
+    assert_eq!(Instruction::Addy(-2).execute(&mut registers), Ok(1));
+    assert_eq!(registers, [15, -2]);
+    assert_eq!(Instruction::Jmp(-4).execute(&mut registers), Ok(-4));
+    assert_eq!(Instruction::Jz(Register::Y, 3).execute(&mut registers), Ok(1));
+    assert_eq!(Instruction::Jnz(Register::Y, 3).execute(&mut registers), Ok(3));
+    registers = [0, 0];
+    assert_eq!(Instruction::Jz(Register::X, 3).execute(&mut registers), Ok(3));
+    assert_eq!(Instruction::Jnz(Register::X, 3).execute(&mut registers), Ok(1));
+}
+
+#[test]
+fn instruction_overflow() {
+    let mut registers = [isize::MAX, isize::MIN];
+    assert_eq!(Instruction::Addx(1).execute(&mut registers), Err(CpuFault::Overflow { register: Register::X }));
+    assert_eq!(Instruction::Mulx(2).execute(&mut registers), Err(CpuFault::Overflow { register: Register::X }));
+    assert_eq!(Instruction::Addy(-1).execute(&mut registers), Err(CpuFault::Overflow { register: Register::Y }));
+    assert_eq!(registers, [isize::MAX, isize::MIN]);
+    let cpu = run("addx 1\nmulx -1\nmulx 9223372036854775807\nmulx 2");
+    assert_eq!(cpu.state(), RunState::Faulted(CpuFault::Overflow { register: Register::X }));
+}
+
+#[test]
+fn cycle_costs() {
+    for (source, cycles) in [
+        ("noop", 1),
+        ("addx 1", 2),
+        ("addy 1", 2),
+        ("mulx 2", 3),
+        ("jmp 1", 1),
+        ("jz x 1", 2),
+        ("jnz x 1", 2),
+    ] {
+        let instruction = Instruction::parse(source, 1).unwrap();
+        assert_eq!(instruction.cycles(), cycles, "{source}");
+        assert_eq!(run(source).cycle, cycles, "{source}");
+    }
+}
+
+#[test]
+fn jumps_and_arithmetic() {
+    // x = (1 + 4) * 3, then y isn't zero so no jump, then x isn't zero so
+    // the addx 1000 gets skipped.
+    let cpu = run("addx 4\nmulx 3\naddy 2\njz y 2\naddx 100\njnz x 2\naddx 1000\nnoop");
+    assert_eq!(cpu.state(), RunState::Halted);
+    assert_eq!(cpu.register(Register::X), 115);
+    assert_eq!(cpu.register(Register::Y), 2);
+    assert_eq!(cpu.cycle, 2 + 3 + 2 + 2 + 2 + 2 + 1);
+}
+
+#[test]
+fn loops() {
+    // Count y up to 3 by looping while x counts down.
+    let cpu = run("addx 2\naddy 1\naddx -1\njnz x -2");
+    assert_eq!(cpu.register(Register::X), 0);
+    assert_eq!(cpu.register(Register::Y), 3);
+}
+
+#[test]
+fn effects_land_after_the_last_cycle() {
+    let mut cpu = CPU::new();
+    cpu.load_program(&parse_program("mulx 5\nnoop").unwrap());
+    let mut seen = Vec::new();
+    while cpu.execute_clock_cycle() == RunState::Running {
+        seen.push((cpu.cycle, cpu.register(Register::X)));
+    }
+    assert_eq!(seen, vec![(1, 1), (2, 1), (3, 1), (4, 5)]);
+}
+
+#[test]
+fn step_by_step() {
+    let mut cpu = loaded("noop\naddx 2");
+    assert_eq!(cpu.step(), StopReason::Step);
+    assert_eq!(cpu.cycle, 1);
+    assert_eq!(cpu.current_instruction(), Some(Instruction::Noop));
+    assert_eq!(cpu.step(), StopReason::Step);
+    assert_eq!(cpu.step(), StopReason::Step);
+    assert_eq!(cpu.step(), StopReason::Stopped(RunState::Halted));
+    assert_eq!(cpu.register(Register::X), 3);
+    // Halted stays halted, without the clock moving.
+    assert_eq!(cpu.step(), StopReason::Stopped(RunState::Halted));
+    assert_eq!(cpu.cycle, 3);
+}
+
+#[test]
+fn cycle_breakpoints() {
+    let mut cpu = example_cpu();
+    cpu.add_breakpoint(Breakpoint::Cycle(20));
+    cpu.add_breakpoint(Breakpoint::Cycle(60));
+    assert_eq!(cpu.resume(), StopReason::Breakpoint(Breakpoint::Cycle(20)));
+    assert_eq!(cpu.get_signal_strength(), 420);
+    assert_eq!(cpu.resume(), StopReason::Breakpoint(Breakpoint::Cycle(60)));
+    assert_eq!(cpu.get_signal_strength(), 1140);
+    assert_eq!(cpu.resume(), StopReason::Stopped(RunState::Halted));
+    assert_eq!(cpu.cycle, 240);
+}
+
+#[test]
+fn register_breakpoints() {
+    let mut cpu = example_cpu();
+    let negative = Breakpoint::Register(Register::X, Ordering::Less, 0);
+    cpu.add_breakpoint(negative);
+    assert_eq!(cpu.resume(), StopReason::Breakpoint(negative));
+    assert!(cpu.register(Register::X) < 0);
+    // Stays hit for as long as x stays negative.
+    assert_eq!(cpu.step(), StopReason::Breakpoint(negative));
+}
+
+#[test]
+fn first_breakpoint_added_wins() {
+    let mut cpu = loaded("noop\nnoop");
+    let x_is_one = Breakpoint::Register(Register::X, Ordering::Equal, 1);
+    cpu.add_breakpoint(Breakpoint::Cycle(1));
+    cpu.add_breakpoint(x_is_one);
+    assert_eq!(cpu.step(), StopReason::Breakpoint(Breakpoint::Cycle(1)));
+    assert_eq!(cpu.step(), StopReason::Breakpoint(x_is_one));
+}
+
+#[test]
+fn removing_breakpoints() {
+    let mut cpu = loaded("noop\nnoop\nnoop");
+    cpu.add_breakpoint(Breakpoint::Cycle(1));
+    cpu.add_breakpoint(Breakpoint::Cycle(2));
+    cpu.remove_breakpoint(&Breakpoint::Cycle(1));
+    assert_eq!(cpu.resume(), StopReason::Breakpoint(Breakpoint::Cycle(2)));
+    cpu.clear_breakpoints();
+    cpu.add_breakpoint(Breakpoint::Cycle(2));
+    cpu.clear_breakpoints();
+    assert_eq!(cpu.resume(), StopReason::Stopped(RunState::Halted));
+}
+
+#[test]
+fn tracing() {
+    let mut cpu = loaded("addx 3\nnoop");
+    assert!(cpu.trace().is_empty());
+    cpu.enable_trace();
+    cpu.resume();
+    let trace: Vec<String> = cpu.trace().iter().map(|entry| entry.to_string()).collect();
+    assert_eq!(
+        trace,
+        vec![
+            "     1  addx 3       x=1 y=0",
+            "     2  addx 3       x=1 y=0",
+            "     3  noop         x=4 y=0",
+        ],
+    );
+
+    let path = std::env::temp_dir().join(format!("aoc2022-trace-{}.txt", std::process::id()));
+    cpu.dump_trace(path.to_str().unwrap()).unwrap();
+    let dumped = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(dumped, format!("{:>6}  {:<12} registers\n{}\n", "cycle", "instruction", trace.join("\n")));
+
+    cpu.disable_trace();
+    assert!(cpu.trace().is_empty());
+}
+
+#[test]
+fn trace_only_covers_cycles_after_enabling() {
+    let mut cpu = loaded("noop\nnoop\nnoop");
+    cpu.step();
+    cpu.enable_trace();
+    cpu.resume();
+    let cycles: Vec<usize> = cpu.trace().iter().map(|entry| entry.cycle).collect();
+    assert_eq!(cycles, vec![2, 3]);
+}
+
+/// The chunks of a PNG as (type, data), checking the signature and CRCs.
+fn png_chunks(png: &[u8]) -> Vec<([u8; 4], Vec<u8>)> {
+    assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+    let mut chunks = Vec::new();
+    let mut rest = &png[8..];
+    while !rest.is_empty() {
+        let len = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+        let kind: [u8; 4] = rest[4..8].try_into().unwrap();
+        let data = rest[8..8 + len].to_vec();
+        let crc = u32::from_be_bytes(rest[8 + len..12 + len].try_into().unwrap());
+        assert_eq!(crc, crc32(&rest[4..8 + len]), "{} CRC", String::from_utf8_lossy(&kind));
+        chunks.push((kind, data));
+        rest = &rest[12 + len..];
+    }
+    chunks
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    // Bytewise table version, so it isn't just the same code twice.
+    let table: Vec<u32> = (0..256u32)
+        .map(|n| (0..8).fold(n, |c, _| if c & 1 == 1 { 0xedb8_8320 ^ (c >> 1) } else { c >> 1 }))
+        .collect();
+    !bytes.iter().fold(0xffff_ffff, |crc, &byte| table[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8))
+}
+
+/// Unpack a zlib stream made of stored (uncompressed) deflate blocks.
+fn inflate_stored(stream: &[u8]) -> Vec<u8> {
+    assert_eq!(stream[0] & 0x0f, 8, "deflate");
+    assert_eq!((stream[0] as u16 * 256 + stream[1] as u16) % 31, 0, "header check");
+    let mut data = Vec::new();
+    let mut at = 2;
+    loop {
+        let header = stream[at];
+        assert_eq!(header >> 1, 0, "stored block");
+        let len = u16::from_le_bytes([stream[at + 1], stream[at + 2]]);
+        let nlen = u16::from_le_bytes([stream[at + 3], stream[at + 4]]);
+        assert_eq!(nlen, !len);
+        data.extend_from_slice(&stream[at + 5..at + 5 + len as usize]);
+        at += 5 + len as usize;
+        if header & 1 == 1 {
+            break
+        }
+    }
+    let (a, b) = data.iter().fold((1u32, 0u32), |(a, b), &byte| {
+        let a = (a + byte as u32) % 65521;
+        (a, (b + a) % 65521)
+    });
+    assert_eq!(&stream[at..], ((b << 16) | a).to_be_bytes(), "adler32");
+    data
+}
+
+/// Width, height and pixels (one byte each) of an 8-bit grayscale PNG.
+fn decode_png(png: &[u8]) -> (usize, usize, Vec<Vec<u8>>) {
+    let chunks = png_chunks(png);
+    let kinds: Vec<&[u8; 4]> = chunks.iter().map(|(kind, _)| kind).collect();
+    assert_eq!(kinds, vec![b"IHDR", b"IDAT", b"IEND"]);
+    let header = &chunks[0].1;
+    let width = u32::from_be_bytes(header[..4].try_into().unwrap()) as usize;
+    let height = u32::from_be_bytes(header[4..8].try_into().unwrap()) as usize;
+    // 8-bit grayscale, deflate, no filtering, not interlaced.
+    assert_eq!(&header[8..], &[8, 0, 0, 0, 0]);
+    assert!(chunks[2].1.is_empty());
+
+    let scanlines = inflate_stored(&chunks[1].1);
+    assert_eq!(scanlines.len(), (width + 1) * height);
+    let rows = scanlines
+        .chunks(width + 1)
+        .map(|scanline| {
+            assert_eq!(scanline[0], 0, "filter type");
+            scanline[1..].to_vec()
+        })
+        .collect();
+    (width, height, rows)
+}
+
+const SMALL_SCREEN: [&str; 2] = ["#..", ".##"];
+
+#[test]
+fn pbm() {
+    let crt = crt_showing(&SMALL_SCREEN);
+    assert_eq!(crt.to_pbm(0), Ok("P1\n3 2\n1 0 0\n0 1 1\n".to_string()));
+}
+
+#[test]
+fn png() {
+    let crt = crt_showing(&SMALL_SCREEN);
+    let (width, height, rows) = decode_png(&crt.to_png(0, 1).unwrap());
+    assert_eq!((width, height), (3, 2));
+    assert_eq!(rows, vec![vec![0, 255, 255], vec![255, 0, 0]]);
+}
+
+#[test]
+fn scaled_png() {
+    let crt = crt_showing(&SMALL_SCREEN);
+    let (width, height, rows) = decode_png(&crt.to_png(0, 2).unwrap());
+    assert_eq!((width, height), (6, 4));
+    assert_eq!(rows[0], vec![0, 0, 255, 255, 255, 255]);
+    assert_eq!(rows[1], rows[0]);
+    assert_eq!(rows[3], vec![255, 255, 0, 0, 0, 0]);
+    // Scale 0 is treated as 1.
+    assert_eq!(crt.to_png(0, 0), crt.to_png(0, 1));
+}
+
+#[test]
+fn big_png_spans_several_blocks() {
+    let mut device = example_device();
+    device.run();
+    // 1601 x 240 bytes of scanlines, far more than one 64K stored block.
+    let (width, height, rows) = decode_png(&device.crt.to_png(0, 40).unwrap());
+    assert_eq!((width, height), (1600, 240));
+    let first_row: Vec<bool> = rows[0].iter().step_by(40).map(|&pixel| pixel == 0).collect();
+    let expected: Vec<bool> = EXAMPLE_SCREEN.lines().next().unwrap().chars().map(|c| c == '#').collect();
+    assert_eq!(first_row, expected);
+}
+
+#[test]
+fn half_blocks() {
+    let crt = crt_showing(&["#.#", ".##", "#.."]);
+    assert_eq!(crt.render_half_blocks(0), Ok("▀▄█\n▀  \n".to_string()));
+}
+
+#[test]
+fn exports_of_missing_frames() {
+    let crt = crt_showing(&SMALL_SCREEN);
+    let missing = NoSuchFrame { frame: 1, n_frames: 1 };
+    assert_eq!(crt.to_pbm(1), Err(missing));
+    assert_eq!(crt.to_png(1, 1), Err(missing));
+    assert_eq!(crt.render_half_blocks(1), Err(missing));
+    let path = std::env::temp_dir().join(format!("aoc2022-missing-{}.png", std::process::id()));
+    let err = crt.write_png(1, 1, path.to_str().unwrap()).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    assert!(!path.exists());
+}
+
+#[test]
+fn replay_is_off_by_default() {
+    let mut device = example_device();
+    device.run();
+    assert!(device.crt.replay_frames().is_empty());
+}
+
+#[test]
+fn replay() {
+    let mut crt = CRT::new(2, 2, vec![0]);
+    crt.lazer_beam_it(1, 0);
+    crt.enable_replay();
+    for (cycle, sprite_pos) in [(2, 1), (3, 5), (4, 1)] {
+        crt.lazer_beam_it(cycle, sprite_pos);
+    }
+    // The pixel from before replay was enabled doesn't show.
+    assert_eq!(crt.replay_frames(), vec![" ▀\n", " ▀\n", " █\n"]);
+    crt.disable_replay();
+    assert!(crt.replay_frames().is_empty());
+}
+
+#[test]
+fn hitting_the_cycle_limit_changes_nothing() {
+    let mut cpu = loaded("addx 5\naddx 1");
+    cpu.set_cycle_limit(2);
+    cpu.resume();
+    assert_eq!(cpu.state(), RunState::Faulted(CpuFault::CycleLimit { limit: 2 }));
+    // The addx 5 never got to finish.
+    assert_eq!(cpu.cycle, 2);
+    assert_eq!(cpu.register(Register::X), 1);
+    assert_eq!(cpu.current_instruction(), Some(Instruction::Addx(5)));
+}
+
+#[test]
+fn trace_recorder_matches_cpu_trace() {
+    let mut device = example_device();
+    device.cpu.enable_trace();
+    device.plug_in(Box::new(TraceRecorder::new()));
+    let outputs = device.run();
+    let PeripheralOutput::Trace(entries) = &outputs[2] else { panic!("expected a trace") };
+    assert_eq!(entries.len(), 240);
+    assert_eq!(entries.as_slice(), device.cpu.trace());
+}
+
+#[test]
+fn jumping_out_of_the_program() {
+    assert_eq!(run("noop\njmp 1000").state(), RunState::Faulted(CpuFault::JumpOutOfBounds { from: 1, to: 1001 }));
+    assert_eq!(run("noop\njmp -2").state(), RunState::Faulted(CpuFault::JumpOutOfBounds { from: 1, to: -1 }));
+    assert_eq!(run("jz x 5").state(), RunState::Halted);
+    assert_eq!(run("addx -1\njz x 5").state(), RunState::Faulted(CpuFault::JumpOutOfBounds { from: 1, to: 6 }));
+}
+
+#[test]
+fn jumping_to_the_end_halts() {
+    let cpu = run("jmp 2\naddx 100");
+    assert_eq!(cpu.state(), RunState::Halted);
+    assert_eq!(cpu.register(Register::X), 1);
+}
+
+#[test]
+fn remaining_cycles() {
+    let mut cpu = loaded("noop\naddx 1\nmulx 2");
+    assert_eq!(cpu.remaining_cycles(), Some(6));
+    cpu.step();
+    assert_eq!(cpu.remaining_cycles(), Some(5));
+    cpu.step();
+    cpu.step();
+    assert_eq!(cpu.remaining_cycles(), Some(3));
+    cpu.resume();
+    assert_eq!(cpu.remaining_cycles(), Some(0));
+    assert_eq!(cpu.cycle, 6);
+
+    // Jumps ahead, or in progress, can't be predicted.
+    let mut cpu = loaded("jz x 2\nnoop\nnoop");
+    assert_eq!(cpu.remaining_cycles(), None);
+    cpu.step();
+    cpu.step();
+    assert_eq!(cpu.remaining_cycles(), None);
+    // x isn't zero, so on to the first noop, with one more after it.
+    cpu.step();
+    assert_eq!(cpu.remaining_cycles(), Some(1));
+}
+
+#[test]
+fn reset_and_reload() {
+    let mut cpu = loaded("addx 4\njmp 1000");
+    cpu.add_breakpoint(Breakpoint::Cycle(2));
+    cpu.enable_trace();
+    assert_eq!(cpu.resume(), StopReason::Breakpoint(Breakpoint::Cycle(2)));
+    assert!(matches!(cpu.resume(), StopReason::Stopped(RunState::Faulted(_))));
+
+    // Same program and breakpoints, fresh everything else.
+    cpu.reset();
+    assert_eq!(cpu.state(), RunState::Running);
+    assert_eq!((cpu.cycle, cpu.register(Register::X), cpu.register(Register::Y)), (0, 1, 0));
+    assert!(cpu.trace().is_empty());
+    assert_eq!(cpu.resume(), StopReason::Breakpoint(Breakpoint::Cycle(2)));
+
+    cpu.reload_program(&parse_program("addy 7").unwrap());
+    assert_eq!(cpu.remaining_cycles(), Some(2));
+    assert_eq!(cpu.resume(), StopReason::Breakpoint(Breakpoint::Cycle(2)));
+    assert_eq!(cpu.resume(), StopReason::Stopped(RunState::Halted));
+    assert_eq!(cpu.register(Register::Y), 7);
+    assert_eq!(cpu.trace().len(), 2);
+}
@@ -0,0 +1,77 @@
+use aoc2022::{
+    ClimbRule,
+    HeightMap,
+};
+
+const EXAMPLE: &str = "Sabqponm\nabcryxxl\naccszExk\nacctuvwj\nabdefghi\n";
+
+#[test]
+fn example_from_start() {
+    let map = HeightMap::parse(EXAMPLE);
+    assert_eq!(map.start, [0, 0]);
+    assert_eq!(map.end, [5, 2]);
+    assert_eq!(map.shortest_distance(&[map.start], map.end, &ClimbRule::default()), Some(31));
+}
+
+#[test]
+fn example_from_every_trailhead() {
+    let map = HeightMap::parse(EXAMPLE);
+    let trailheads = map.cells_at_elevation('a');
+    assert_eq!(trailheads.len(), 6);
+    assert_eq!(map.shortest_distance(&trailheads, map.end, &ClimbRule::default()), Some(29));
+}
+
+#[test]
+fn path_follows_the_rule() {
+    let map = HeightMap::parse(EXAMPLE);
+    let rule = ClimbRule::default();
+    let path = map.find_path(&[map.start], map.end, &rule).unwrap();
+    assert_eq!(path.first(), Some(&map.start));
+    assert_eq!(path.last(), Some(&map.end));
+    for step in path.windows(2) {
+        let [[x0, y0], [x1, y1]] = [step[0], step[1]];
+        assert_eq!((x1 - x0).abs() + (y1 - y0).abs(), 1);
+        assert!(rule.allows(map.elevation(&step[0]).unwrap(), map.elevation(&step[1]).unwrap()));
+    }
+}
+
+#[test]
+fn start_is_the_target() {
+    let map = HeightMap::parse(EXAMPLE);
+    assert_eq!(map.find_path(&[map.end], map.end, &ClimbRule::default()), Some(vec![map.end]));
+}
+
+#[test]
+fn unreachable_target() {
+    // Nothing climbs straight from `a` to `z`.
+    let map = HeightMap::parse("SzE");
+    assert_eq!(map.find_path(&[map.start], map.end, &ClimbRule::default()), None);
+    assert_eq!(map.shortest_distance(&[map.start], map.end, &ClimbRule::default()), None);
+}
+
+#[test]
+fn sources_off_the_map_are_ignored() {
+    let map = HeightMap::parse(EXAMPLE);
+    assert_eq!(map.shortest_distance(&[[-1, -1], [100, 0]], map.end, &ClimbRule::default()), None);
+    assert_eq!(map.shortest_distance(&[[-1, -1], map.start], map.end, &ClimbRule::default()), Some(31));
+}
+
+#[test]
+fn anything_goes() {
+    // With no limits the shortest path is as the crow flies (orthogonally).
+    let map = HeightMap::parse(EXAMPLE);
+    let rule = ClimbRule::new(isize::MAX, isize::MAX);
+    assert_eq!(map.shortest_distance(&[map.start], map.end, &rule), Some(7));
+}
+
+#[test]
+fn careful_descent() {
+    // Down one at most: the only way from the summit is back along the ridge.
+    let map = HeightMap::parse("SbcdE");
+    assert_eq!(map.shortest_distance(&[map.end], map.start, &ClimbRule::default()), Some(4));
+    assert_eq!(map.shortest_distance(&[map.end], map.start, &ClimbRule::new(1, 1)), None);
+    let map = HeightMap::parse("Sbcdefghijklmnopqrstuvwxy\nzzzzzzzzzzzzzzzzzzzzzzzzE");
+    // Walking down the alphabet one letter a step works; keeping level doesn't.
+    assert_eq!(map.shortest_distance(&[map.end], map.start, &ClimbRule::new(0, 1)), Some(25));
+    assert_eq!(map.shortest_distance(&[map.end], map.start, &ClimbRule::new(0, 0)), None);
+}
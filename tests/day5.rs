@@ -0,0 +1,154 @@
+use aoc2022::{
+    Crane,
+    CraneMove,
+    CrateYard,
+    CrateYardError,
+};
+
+const EXAMPLE: &str = "    [D]    
+[N] [C]    
+[Z] [M] [P]
+ 1   2   3 
+
+move 1 from 2 to 1
+move 3 from 1 to 3
+move 2 from 2 to 1
+move 1 from 1 to 2
+";
+
+fn tops_after(input: &str, crane: Crane) -> String {
+    let (mut yard, moves) = CrateYard::parse(input).unwrap();
+    yard.apply_moves(&moves, crane).unwrap();
+    yard.tops()
+}
+
+fn parse_error(input: &str) -> CrateYardError {
+    CrateYard::parse(input).err().unwrap()
+}
+
+fn move_error(input: &str) -> CrateYardError {
+    let (mut yard, moves) = CrateYard::parse(input).unwrap();
+    yard.apply_moves(&moves, Crane::default()).err().unwrap()
+}
+
+#[test]
+fn example() {
+    assert_eq!(tops_after(EXAMPLE, Crane::CrateMover9000), "CMZ");
+    assert_eq!(tops_after(EXAMPLE, Crane::CrateMover9001), "MCD");
+}
+
+#[test]
+fn example_drawing() {
+    let (yard, moves) = CrateYard::parse(EXAMPLE).unwrap();
+    assert_eq!(yard.stack_ids().collect::<Vec<_>>(), vec![1, 2, 3]);
+    assert_eq!(yard.stack(2), Some(&["M".to_string(), "C".to_string(), "D".to_string()][..]));
+    assert_eq!(moves[0], CraneMove { count: 1, from: 2, to: 1, line: 6 });
+    assert_eq!(yard.to_string(), "    [D]\n[N] [C]\n[Z] [M] [P]\n 1   2   3");
+}
+
+#[test]
+fn ragged_lines() {
+    // Trailing spaces stripped, like most editors would.
+    let input = "    [D]\n[N] [C]\n[Z] [M] [P]\n 1   2   3\n\nmove 1 from 2 to 1\nmove 3 from 1 to 3\nmove 2 from 2 to 1\nmove 1 from 1 to 2";
+    assert_eq!(tops_after(input, Crane::CrateMover9000), "CMZ");
+}
+
+#[test]
+fn multi_character_labels() {
+    let (yard, _) = CrateYard::parse("      [BB]\n[AAA] [C]\n  1    2").unwrap();
+    assert_eq!(yard.stack(1), Some(&["AAA".to_string()][..]));
+    assert_eq!(yard.stack(2), Some(&["C".to_string(), "BB".to_string()][..]));
+    assert_eq!(yard.tops(), "AAABB");
+}
+
+#[test]
+fn non_sequential_stack_numbers() {
+    let input = "[A]     [C]\n[B] [D] [E]\n 7  10   3\n\nmove 2 from 7 to 3\nmove 1 from 10 to 7";
+    let (yard, _) = CrateYard::parse(input).unwrap();
+    assert_eq!(yard.stack_ids().collect::<Vec<_>>(), vec![7, 10, 3]);
+    assert_eq!(tops_after(input, Crane::CrateMover9000), "DB");
+    assert_eq!(tops_after(input, Crane::CrateMover9001), "DA");
+}
+
+#[test]
+fn empty_stacks_have_no_top() {
+    let (yard, _) = CrateYard::parse("[A]\n 1   2").unwrap();
+    assert_eq!(yard.stack(2), Some(&[][..]));
+    assert_eq!(yard.tops(), "A");
+    assert_eq!(yard.stack(3), None);
+}
+
+#[test]
+fn moving_onto_the_same_stack() {
+    for crane in [Crane::CrateMover9000, Crane::CrateMover9001] {
+        let (mut yard, moves) = CrateYard::parse("[A]\n[B]\n 1\n\nmove 2 from 1 to 1").unwrap();
+        yard.apply_moves(&moves, crane).unwrap();
+        assert_eq!(yard.stack(1), Some(&["B".to_string(), "A".to_string()][..]));
+    }
+}
+
+#[test]
+fn missing_labels() {
+    assert_eq!(parse_error(""), CrateYardError::MissingLabels);
+    assert_eq!(parse_error("\nmove 1 from 1 to 2"), CrateYardError::MissingLabels);
+}
+
+#[test]
+fn bad_stack_label() {
+    assert_eq!(parse_error("[A]\n a"), CrateYardError::BadStackLabel { line: 2, label: "a".to_string() });
+}
+
+#[test]
+fn duplicate_stack() {
+    assert_eq!(parse_error("[A] [B]\n 1   1"), CrateYardError::DuplicateStack { line: 2, stack: 1 });
+}
+
+#[test]
+fn unclosed_crate() {
+    assert_eq!(parse_error("[A\n 1"), CrateYardError::UnclosedCrate { line: 1, column: 1 });
+}
+
+#[test]
+fn stray_character() {
+    assert_eq!(parse_error(" x\n 1"), CrateYardError::StrayCharacter { line: 1, column: 2, character: 'x' });
+}
+
+#[test]
+fn stray_crate() {
+    assert_eq!(parse_error("        [A]\n 1"), CrateYardError::StrayCrate { line: 1, column: 9 });
+}
+
+#[test]
+fn crate_collision() {
+    // Both crates sit over the one wide label.
+    assert_eq!(parse_error("[A][B]\n 100"), CrateYardError::CrateCollision { line: 1, stack: 100 });
+}
+
+#[test]
+fn bad_move() {
+    assert_eq!(parse_error("[A]\n 1\n\nmove x from 1 to 1"), CrateYardError::BadMove { line: 4 });
+    assert_eq!(parse_error("[A]\n 1\n\nshove 1 from 1 to 1"), CrateYardError::BadMove { line: 4 });
+}
+
+#[test]
+fn unknown_stack() {
+    assert_eq!(move_error("[A]\n 1\n\nmove 1 from 1 to 9"), CrateYardError::UnknownStack { line: 4, stack: 9 });
+    assert_eq!(move_error("[A]\n 1\n\nmove 1 from 9 to 1"), CrateYardError::UnknownStack { line: 4, stack: 9 });
+}
+
+#[test]
+fn not_enough_crates() {
+    assert_eq!(
+        move_error("[A]\n 1\n\nmove 2 from 1 to 1"),
+        CrateYardError::NotEnoughCrates { line: 4, stack: 1, wanted: 2, available: 1 },
+    );
+}
+
+#[test]
+fn failed_moves_change_nothing() {
+    let (mut yard, _) = CrateYard::parse("[A]\n 1   2").unwrap();
+    let before = yard.clone();
+    let bad = CraneMove { count: 2, from: 1, to: 2, line: 1 };
+    assert!(yard.apply(&bad, Crane::CrateMover9001).is_err());
+    assert_eq!(yard, before);
+}
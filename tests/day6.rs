@@ -0,0 +1,123 @@
+use aoc2022::{
+    find_all_markers,
+    find_marker,
+    Marker,
+    MarkerDetector,
+    MarkerKind,
+    MarkerScanner,
+    MarkerStream,
+};
+
+const EXAMPLES: [(&str, usize, usize); 5] = [
+    ("mjqjpqmgbljsphdztnvjfqwrcgsmlb", 7, 19),
+    ("bvwbjplbgvbhsrlpgdmjqwftvncz", 5, 23),
+    ("nppdvjthqldpwncqszvftbrmjlhg", 6, 23),
+    ("nznrnfrfntjfmvfwmzdfjlvtqnbhcprsg", 10, 29),
+    ("zcfzfwzzqfrljwzlrfnpqdbhtmscgvjw", 11, 26),
+];
+
+/// The obvious quadratic version, to check against.
+fn brute_force(datastream: &str, marker_size: usize) -> Vec<usize> {
+    let bytes = datastream.as_bytes();
+    if marker_size == 0 {
+        return Vec::new()
+    }
+    (marker_size..=bytes.len())
+        .filter(|&end| {
+            let window = &bytes[end - marker_size..end];
+            window.iter().enumerate().all(|(i, byte)| !window[..i].contains(byte))
+        })
+        .collect()
+}
+
+#[test]
+fn examples() {
+    for (datastream, packet, message) in EXAMPLES {
+        assert_eq!(find_marker(datastream, 4), Some(packet));
+        assert_eq!(find_marker(datastream, 14), Some(message));
+    }
+}
+
+#[test]
+fn marker_at_the_very_start() {
+    assert_eq!(find_marker("abcdaaaa", 4), Some(4));
+}
+
+#[test]
+fn marker_at_the_very_end() {
+    assert_eq!(find_marker("abcd", 4), Some(4));
+    assert_eq!(find_marker("aaaaabcd", 4), Some(8));
+}
+
+#[test]
+fn no_marker() {
+    assert_eq!(find_marker("", 4), None);
+    assert_eq!(find_marker("abc", 4), None);
+    assert_eq!(find_marker("abcabcabc", 4), None);
+    assert_eq!(find_all_markers("aaaa", 2), Vec::<usize>::new());
+}
+
+#[test]
+fn zero_size_marker() {
+    for datastream in ["", "a", "abcd"] {
+        assert_eq!(find_marker(datastream, 0), None);
+        assert!(find_all_markers(datastream, 0).is_empty());
+    }
+}
+
+#[test]
+fn single_character_marker() {
+    assert_eq!(find_marker("a", 1), Some(1));
+    assert_eq!(find_all_markers("aab", 1), vec![1, 2, 3]);
+}
+
+#[test]
+fn marker_longer_than_alphabet() {
+    // There are only 256 different bytes, so 256 is the biggest marker going.
+    let bytes: Vec<u8> = (0..=255u8).collect();
+    let mut detector = MarkerDetector::new(256);
+    assert_eq!(bytes.iter().filter_map(|&byte| detector.push(byte)).next(), Some(256));
+    let mut detector = MarkerDetector::new(257);
+    assert!(bytes.iter().chain(&bytes).all(|&byte| detector.push(byte).is_none()));
+}
+
+#[test]
+fn matches_brute_force() {
+    let datastreams = ["", "a", "ab", "aa", "abcd", "abca", "abcabcd", "aabbccddee", "abcdefgabcdefg"];
+    for datastream in datastreams.iter().copied().chain(EXAMPLES.iter().map(|example| example.0)) {
+        for marker_size in 0..=datastream.len() + 1 {
+            let expected = brute_force(datastream, marker_size);
+            assert_eq!(find_all_markers(datastream, marker_size), expected, "{datastream:?} size {marker_size}");
+            assert_eq!(find_marker(datastream, marker_size), expected.first().copied());
+        }
+    }
+}
+
+#[test]
+fn detector_position() {
+    let mut detector = MarkerDetector::new(2);
+    assert_eq!(detector.push(b'a'), None);
+    assert_eq!(detector.push(b'a'), None);
+    assert_eq!(detector.push(b'b'), Some(3));
+    assert_eq!(detector.position(), 3);
+}
+
+#[test]
+fn scanner_and_stream_agree() {
+    for (datastream, packet, message) in EXAMPLES {
+        let expected = vec![
+            Marker { kind: MarkerKind::Packet, position: packet },
+            Marker { kind: MarkerKind::Message, position: message },
+        ];
+        let scanned: Vec<Marker> = MarkerScanner::new().scan_bytes(datastream.bytes()).collect();
+        assert_eq!(scanned, expected);
+        let streamed: Vec<Marker> = MarkerStream::new(datastream.as_bytes()).map(Result::unwrap).collect();
+        assert_eq!(streamed, expected);
+    }
+}
+
+#[test]
+fn stream_without_markers() {
+    assert_eq!(MarkerStream::new("abcabc".as_bytes()).count(), 0);
+    assert_eq!(MarkerStream::new(&b""[..]).count(), 0);
+}
@@ -0,0 +1,262 @@
+use std::fs;
+use std::io;
+
+use aoc2022::{
+    FsNodeKind,
+    FsTree,
+    TranscriptWarning,
+};
+
+const EXAMPLE: &str = "\
+$ cd /
+$ ls
+dir a
+14848514 b.txt
+8504156 c.dat
+dir d
+$ cd a
+$ ls
+dir e
+29116 f
+2557 g
+62596 h.lst
+$ cd e
+$ ls
+584 i
+$ cd ..
+$ cd ..
+$ cd d
+$ ls
+4060174 j
+8033020 d.log
+5626152 d.ext
+7214296 k
+";
+
+fn size_of(tree: &FsTree, path: &str) -> usize {
+    tree.size(tree.lookup(path).unwrap())
+}
+
+#[test]
+fn example() {
+    let (tree, warnings) = FsTree::parse_transcript(EXAMPLE);
+    assert!(warnings.is_empty());
+    let small: usize = tree.du().into_iter().map(|(_, size)| size).filter(|&size| size <= 100000).sum();
+    assert_eq!(small, 95437);
+    let report = tree.deletion_report(70000000, 30000000);
+    assert_eq!(report.candidates.first(), Some(&("/d".to_string(), 24933642)));
+    assert_eq!(size_of(&tree, "/a/e"), 584);
+    assert_eq!(size_of(&tree, "/"), 48381165);
+}
+
+#[test]
+fn cd_up_at_root_stays_put() {
+    let (tree, warnings) = FsTree::parse_transcript("$ cd /\n$ cd ..\n$ cd ..\n$ ls\n10 a\n");
+    assert!(warnings.is_empty());
+    assert_eq!(tree.path(tree.lookup("/a").unwrap()), "/a");
+    assert_eq!(size_of(&tree, "/"), 10);
+}
+
+#[test]
+fn multi_segment_paths() {
+    let transcript = "$ cd /a/b\n$ ls\n1 x\n$ cd ../c/./d\n$ ls\n2 y\n$ cd /\n$ cd a/b\n$ ls\n1 x\n";
+    let (tree, warnings) = FsTree::parse_transcript(transcript);
+    assert!(warnings.is_empty());
+    assert_eq!(size_of(&tree, "/a/b/x"), 1);
+    assert_eq!(size_of(&tree, "/a/c/d/y"), 2);
+    assert_eq!(size_of(&tree, "/a"), 3);
+}
+
+#[test]
+fn relisting_doesnt_double_count() {
+    let transcript = "$ cd /\n$ ls\ndir a\n5 b\n$ ls\ndir a\n5 b\n$ cd a\n$ ls\n7 c\n$ cd /\n$ cd a\n$ ls\n7 c\n";
+    let (tree, warnings) = FsTree::parse_transcript(transcript);
+    assert!(warnings.is_empty());
+    assert_eq!(size_of(&tree, "/"), 12);
+    assert_eq!(tree.n_nodes(), 4);
+}
+
+#[test]
+fn relisting_with_a_new_size() {
+    let (tree, warnings) = FsTree::parse_transcript("$ ls\n5 b\n$ ls\n6 b\n");
+    assert_eq!(warnings, vec![TranscriptWarning::SizeChanged { line: 4, path: "/b".to_string(), old: 5, new: 6 }]);
+    assert_eq!(size_of(&tree, "/"), 6);
+}
+
+#[test]
+fn empty_directories() {
+    let (tree, warnings) = FsTree::parse_transcript("$ cd /\n$ ls\ndir empty\n$ cd empty\n$ ls\n");
+    assert!(warnings.is_empty());
+    assert_eq!(size_of(&tree, "/empty"), 0);
+    assert!(tree.du().contains(&("/empty".to_string(), 0)));
+    assert_eq!(FsTree::new().du(), vec![("/".to_string(), 0)]);
+}
+
+#[test]
+fn unknown_commands() {
+    let (tree, warnings) = FsTree::parse_transcript("$ cd /\n$ pwd\n/\n$ ls -la\n$ ls\n3 a\n");
+    assert_eq!(
+        warnings,
+        vec![
+            TranscriptWarning::UnknownCommand { line: 2, command: "pwd".to_string() },
+            TranscriptWarning::UnexpectedOutput { line: 3, output: "/".to_string() },
+            TranscriptWarning::UnknownCommand { line: 4, command: "ls -la".to_string() },
+        ],
+    );
+    assert_eq!(size_of(&tree, "/"), 3);
+}
+
+#[test]
+fn kind_conflicts() {
+    let (tree, warnings) = FsTree::parse_transcript("$ ls\n5 a\ndir a\n$ cd a\n");
+    assert_eq!(
+        warnings,
+        vec![
+            TranscriptWarning::KindConflict { line: 3, path: "/a".to_string() },
+            TranscriptWarning::KindConflict { line: 4, path: "/a".to_string() },
+        ],
+    );
+    assert_eq!(size_of(&tree, "/"), 5);
+}
+
+#[test]
+fn malformed_names() {
+    let transcript = "$ ls\ndir ..\n12 ../../escape.txt\ndir .\n3 a/b\ndir \n4\nbig c\n";
+    let (tree, warnings) = FsTree::parse_transcript(transcript);
+    let lines: Vec<usize> = warnings
+        .iter()
+        .map(|warning| match warning {
+            TranscriptWarning::MalformedOutput { line, .. } => *line,
+            other => panic!("unexpected warning {other}"),
+        })
+        .collect();
+    assert_eq!(lines, vec![2, 3, 4, 5, 6, 7, 8]);
+    assert_eq!(tree.n_nodes(), 1);
+}
+
+/// The example plus names with spaces and an empty directory. (Lines are
+/// trimmed, so names can't end in a space.)
+fn awkward_tree() -> FsTree {
+    let mut tree = FsTree::from_transcript(EXAMPLE);
+    let my_dir = tree.add_dir(FsTree::ROOT, "my dir");
+    tree.add_file(my_dir, "x y.txt", 42);
+    let nested = tree.add_dir(my_dir, "  nested");
+    tree.add_file(nested, "z", 7);
+    tree.add_dir(FsTree::ROOT, "empty");
+    tree
+}
+
+#[test]
+fn cd_into_names_with_spaces() {
+    let (tree, warnings) = FsTree::parse_transcript("$ ls\ndir my dir\n$ cd my dir\n$ ls\n5 x\n");
+    assert!(warnings.is_empty());
+    assert_eq!(size_of(&tree, "/my dir/x"), 5);
+    assert_eq!(tree.node(FsTree::ROOT).children.len(), 1);
+}
+
+#[test]
+fn transcript_round_trip() {
+    let tree = awkward_tree();
+    let (rebuilt, warnings) = FsTree::parse_transcript(&tree.to_transcript());
+    assert!(warnings.is_empty());
+    assert_eq!(rebuilt.render_tree(), tree.render_tree());
+    assert_eq!(rebuilt.du(), tree.du());
+}
+
+#[test]
+fn directory_round_trip() {
+    let tree = awkward_tree();
+    let root = tree.materialize_in_temp_dir().unwrap();
+    let read_back = FsTree::from_directory(&root);
+    let transcript = FsTree::transcript_from_directory(&root);
+    fs::remove_dir_all(&root).unwrap();
+
+    // Entries come back in name order, so compare what doesn't depend on it.
+    let read_back = read_back.unwrap();
+    assert_eq!(read_back.du(), tree.du());
+    assert_eq!(read_back.n_nodes(), tree.n_nodes());
+    assert_eq!(size_of(&read_back, "/my dir/  nested/z"), 7);
+    assert_eq!(FsTree::from_transcript(&transcript.unwrap()).du(), tree.du());
+}
+
+#[test]
+#[should_panic]
+fn names_cant_climb_out() {
+    FsTree::new().add_file(FsTree::ROOT, "../escape.txt", 12);
+}
+
+/// (path, size, type flag) for each entry of a ustar archive, checking each
+/// header's checksum on the way.
+fn read_tar(archive: &[u8]) -> Vec<(String, usize, u8)> {
+    assert_eq!(archive.len() % 512, 0);
+    let field = |header: &[u8], range: std::ops::Range<usize>| {
+        let bytes = &header[range];
+        let end = bytes.iter().position(|&byte| byte == 0).unwrap_or(bytes.len());
+        String::from_utf8(bytes[..end].to_vec()).unwrap()
+    };
+    let mut entries = Vec::new();
+    let mut offset = 0;
+    while archive[offset..offset + 512].iter().any(|&byte| byte != 0) {
+        let header = &archive[offset..offset + 512];
+        let sum: u32 = header
+            .iter()
+            .enumerate()
+            .map(|(idx, &byte)| if (148..156).contains(&idx) { b' ' as u32 } else { byte as u32 })
+            .sum();
+        assert_eq!(u32::from_str_radix(field(header, 148..154).as_str(), 8), Ok(sum));
+        assert_eq!(&header[257..263], b"ustar\0");
+
+        let (prefix, name) = (field(header, 345..500), field(header, 0..100));
+        let path = if prefix.is_empty() { name } else { format!("{prefix}/{name}") };
+        let size = usize::from_str_radix(field(header, 124..135).as_str(), 8).unwrap();
+        entries.push((path, size, header[156]));
+        offset += 512 + size.div_ceil(512) * 512;
+    }
+    // Two empty blocks to finish.
+    assert_eq!(archive.len(), offset + 1024);
+    entries
+}
+
+#[test]
+fn tar_round_trip() {
+    let mut tree = awkward_tree();
+    // Too long for the name field alone, and byte 156 is in the middle of an
+    // `é`, so the split has to be found without slicing there.
+    let long_dir = tree.add_dir(FsTree::ROOT, &"é".repeat(40));
+    let longer_dir = tree.add_dir(long_dir, &"é".repeat(40));
+    tree.add_file(longer_dir, "f", 600);
+
+    let mut archive = Vec::new();
+    tree.write_tar(&mut archive).unwrap();
+    let entries = read_tar(&archive);
+
+    let expected: Vec<(String, usize, u8)> = tree
+        .walk(FsTree::ROOT)
+        .skip(1)
+        .map(|(id, _)| {
+            let path = tree.path(id).trim_start_matches('/').to_string();
+            match tree.node(id).kind {
+                FsNodeKind::File { size } => (path, size, b'0'),
+                FsNodeKind::Dir => (path + "/", 0, b'5'),
+            }
+        })
+        .collect();
+    assert_eq!(entries, expected);
+    let long_path = format!("{0}/{0}/f", "é".repeat(40));
+    assert!(long_path.len() > 156);
+    assert!(entries.contains(&(long_path, 600, b'0')));
+}
+
+#[test]
+fn tar_rejects_what_wont_fit() {
+    let mut tree = FsTree::new();
+    tree.add_file(FsTree::ROOT, &"x".repeat(101), 1);
+    let error = tree.write_tar(io::sink()).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+
+    // The size field tops out just under 8 GiB. Nothing gets written for it.
+    let mut tree = FsTree::new();
+    tree.add_file(FsTree::ROOT, "huge", 8 << 30);
+    let error = tree.write_tar(io::sink()).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+}
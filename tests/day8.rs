@@ -0,0 +1,69 @@
+use aoc2022::{
+    Forest,
+    GridError,
+};
+
+const EXAMPLE: &str = "30373\n25512\n65332\n33549\n35390\n";
+
+#[test]
+fn example() {
+    let forest = Forest::parse(EXAMPLE).unwrap();
+    assert_eq!(forest.n_visible(), 21);
+    assert_eq!(forest.best_scenic_score(), Some(8));
+}
+
+#[test]
+fn empty_forest() {
+    for map in ["", "\n", "\n\n  \n"] {
+        let forest = Forest::parse(map).unwrap();
+        assert_eq!(forest.n_visible(), 0);
+        assert_eq!(forest.best_scenic_score(), None);
+        assert_eq!(forest.visibility().width(), 0);
+    }
+}
+
+#[test]
+fn single_tree() {
+    let forest = Forest::parse("7").unwrap();
+    assert_eq!(forest.n_visible(), 1);
+    assert_eq!(forest.best_scenic_score(), Some(0));
+}
+
+#[test]
+fn single_row_and_column() {
+    // Every tree is on an edge, so all are visible and none can score.
+    for map in ["31415", "3\n1\n4\n1\n5"] {
+        let forest = Forest::parse(map).unwrap();
+        assert_eq!(forest.n_visible(), 5);
+        assert_eq!(forest.best_scenic_score(), Some(0));
+    }
+}
+
+#[test]
+fn two_by_two() {
+    let forest = Forest::parse("99\n99").unwrap();
+    assert_eq!(forest.n_visible(), 4);
+    assert_eq!(forest.best_scenic_score(), Some(0));
+}
+
+#[test]
+fn hidden_center() {
+    let forest = Forest::parse("999\n919\n999").unwrap();
+    assert_eq!(forest.n_visible(), 8);
+    assert_eq!(forest.best_scenic_score(), Some(1));
+}
+
+#[test]
+fn ragged_rows() {
+    let err = Forest::parse("123\n45\n678").err().unwrap();
+    assert_eq!(err, GridError::RaggedRow { line: 2, expected: 3, found: 2 });
+    // Line numbers count blank lines too.
+    let err = Forest::parse("12\n\n345").err().unwrap();
+    assert_eq!(err, GridError::RaggedRow { line: 3, expected: 2, found: 3 });
+}
+
+#[test]
+fn bad_heights() {
+    let err = Forest::parse("123\n4x6").err().unwrap();
+    assert_eq!(err, GridError::BadCell { line: 2, column: 2, character: 'x' });
+}
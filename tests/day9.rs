@@ -0,0 +1,131 @@
+use aoc2022::{
+    Direction,
+    FollowRule,
+    Rope,
+    RopeMove,
+    RopeMoveError,
+    parse_rope_moves,
+};
+
+const EXAMPLE: &str = "R 4\nU 4\nL 3\nD 1\nR 4\nD 1\nL 5\nR 2\n";
+const LARGER_EXAMPLE: &str = "R 5\nU 8\nL 8\nD 3\nR 17\nD 10\nL 25\nU 20\n";
+
+fn moved(n_knots: usize, moves: &str) -> Rope {
+    let mut rope = Rope::new(n_knots);
+    rope.apply_moves(&parse_rope_moves(moves).unwrap());
+    rope
+}
+
+#[test]
+fn example() {
+    assert_eq!(moved(2, EXAMPLE).n_visited(1), 13);
+    assert_eq!(moved(10, EXAMPLE).n_visited(9), 1);
+}
+
+#[test]
+fn larger_example() {
+    assert_eq!(moved(10, LARGER_EXAMPLE).n_visited(9), 36);
+}
+
+#[test]
+fn every_knot_of_a_long_rope() {
+    // Knot 1 of a 10-knot rope moves just like the tail of a 2-knot rope.
+    let rope = moved(10, EXAMPLE);
+    assert_eq!(rope.n_visited(1), 13);
+    assert_eq!(rope.n_visited(0), moved(1, EXAMPLE).n_visited(0));
+    assert_eq!([rope.tail().x, rope.tail().y], [0, 0]);
+}
+
+#[test]
+fn parse_diagonals() {
+    for (source, direction) in [
+        ("UL 1", Direction::UpLeft),
+        ("LU 1", Direction::UpLeft),
+        ("UR 1", Direction::UpRight),
+        ("RU 1", Direction::UpRight),
+        ("DL 1", Direction::DownLeft),
+        ("LD 1", Direction::DownLeft),
+        ("DR 1", Direction::DownRight),
+        ("RD 1", Direction::DownRight),
+    ] {
+        assert_eq!(parse_rope_moves(source), Ok(vec![RopeMove { direction, steps: 1 }]));
+    }
+}
+
+#[test]
+fn parse_compound_moves_and_comments() {
+    let moves = parse_rope_moves("R 4, U 2\n\nD3  # down a bit\n# just a comment\n").unwrap();
+    assert_eq!(moves, vec![
+        RopeMove { direction: Direction::Right, steps: 4 },
+        RopeMove { direction: Direction::Up, steps: 2 },
+        RopeMove { direction: Direction::Down, steps: 3 },
+    ]);
+    let listed = moves.iter().map(RopeMove::to_string).collect::<Vec<_>>();
+    assert_eq!(listed, ["R 4", "U 2", "D 3"]);
+}
+
+#[test]
+fn diagonal_moves() {
+    let rope = moved(2, "UR 3");
+    assert_eq!([rope.head().x, rope.head().y], [3, 3]);
+    assert_eq!([rope.tail().x, rope.tail().y], [2, 2]);
+    assert_eq!(rope.n_visited(1), 3);
+}
+
+#[test]
+fn parse_errors() {
+    assert_eq!(
+        parse_rope_moves("R 4\nX 2"),
+        Err(RopeMoveError::UnknownDirection { line: 2, direction: "X".to_string() }),
+    );
+    assert_eq!(
+        parse_rope_moves("R 4\nU 2\n\nL  # how far?"),
+        Err(RopeMoveError::MissingStepCount { line: 4 }),
+    );
+    assert_eq!(
+        parse_rope_moves("R 4, U two"),
+        Err(RopeMoveError::BadStepCount { line: 1, steps: "two".to_string() }),
+    );
+    assert_eq!(
+        RopeMoveError::BadStepCount { line: 1, steps: "two".to_string() }.to_string(),
+        "line 1: bad step count `two`",
+    );
+}
+
+#[test]
+fn orthogonal_rule() {
+    let mut rope = Rope::with_rule(2, FollowRule::Orthogonal);
+    rope.apply_moves(&parse_rope_moves("R 2, U 1").unwrap());
+    // Touching diagonally isn't good enough, and the catch up is sideways.
+    assert_eq!([rope.tail().x, rope.tail().y], [2, 0]);
+    assert_eq!(rope.n_visited(1), 3);
+}
+
+#[test]
+fn slack_rule() {
+    let mut rope = Rope::with_rule(2, FollowRule::Slack(2));
+    rope.apply_moves(&parse_rope_moves("R 2").unwrap());
+    assert_eq!(rope.n_visited(1), 1);
+    rope.apply_moves(&parse_rope_moves("R 1").unwrap());
+    assert_eq!([rope.tail().x, rope.tail().y], [1, 0]);
+}
+
+#[test]
+fn lagged_rule() {
+    let mut rope = Rope::new(2);
+    rope.set_rule(1, FollowRule::Lagged(2));
+    rope.apply_moves(&parse_rope_moves("R 1, U 1").unwrap());
+    assert_eq!([rope.tail().x, rope.tail().y], [0, 0]);
+    rope.apply_moves(&parse_rope_moves("R 1").unwrap());
+    assert_eq!([rope.tail().x, rope.tail().y], [1, 0]);
+    rope.apply_moves(&parse_rope_moves("R 1").unwrap());
+    // Walks the leader's corner rather than cutting it.
+    assert_eq!([rope.tail().x, rope.tail().y], [1, 1]);
+}
+
+#[test]
+fn taut_is_the_default() {
+    let mut taut = Rope::with_rule(3, FollowRule::Taut);
+    taut.apply_moves(&parse_rope_moves(EXAMPLE).unwrap());
+    assert_eq!(taut.n_visited(2), moved(3, EXAMPLE).n_visited(2));
+}